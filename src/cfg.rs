@@ -0,0 +1,379 @@
+//! Control-flow graph of a [`SyntaxTree`]
+//!
+//! Lines are grouped in basic blocks, which are always executed from their first line to their
+//! last one. A block starts at the first line, at the target of a `GOTO` and after a line which can
+//! jump or end the program. Empty lines belong to no block.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    graphviz::{Digraph, Node},
+    syntax_tree::{Line, Stmt, StmtKind, SyntaxTree},
+};
+
+/// Identifies a [`Block`], this is also its index in [`Cfg::blocks`]
+pub type BlockId = usize;
+
+/// A possible path from a block to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub to: BlockId,
+    /// The condition to take this edge, [`None`] if it is always taken
+    pub cond: Option<String>,
+}
+
+/// Consecutive lines always executed from the first one to the last one
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// Indexes of the lines in [`SyntaxTree::lines`], in order
+    pub lines: Vec<usize>,
+    /// The blocks that can be executed after this one
+    pub successors: Vec<Edge>,
+}
+
+/// The blocks of a program and the paths between them
+///
+/// The first block is the entry, the last one is an empty block standing for the end of the
+/// program.
+pub struct Cfg<'a> {
+    lines: &'a [Line],
+    pub blocks: Vec<Block>,
+}
+
+/// A natural loop, made of the blocks from which a jump back to its header can be reached without
+/// going through the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The only block of the loop reached from outside of it
+    pub header: BlockId,
+    /// Every block of the loop, including the header, in order
+    pub blocks: Vec<BlockId>,
+    /// The blocks jumping back to the header
+    pub latches: Vec<BlockId>,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.binary_search(&block).is_ok()
+    }
+}
+
+/// Which blocks every path from the entry to a block goes through
+pub struct Dominators(Vec<Vec<bool>>);
+
+impl Dominators {
+    /// Checks if every path from the entry to `block` goes through `dominator`
+    ///
+    /// A block dominates itself, and blocks that cannot be reached are dominated by every block.
+    pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        self.0[block][dominator]
+    }
+}
+
+/// Where the execution goes after a statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// On to the next line
+    Next,
+    /// To a line number, with `GOTO`
+    Line(u32),
+    /// Out of the program, with `END`
+    End,
+}
+
+impl<'a> Cfg<'a> {
+    pub fn new(ast: &'a SyntaxTree) -> Self {
+        let lines = ast.lines();
+        let code: Vec<usize> = (0..lines.len())
+            .filter(|index| !lines[*index].is_empty())
+            .collect();
+
+        let mut leaders = BTreeSet::new();
+        leaders.extend(code.first());
+        for (position, index) in code.iter().enumerate() {
+            let exits = exits(lines[*index].stmt());
+
+            for (exit, _) in &exits {
+                if let Exit::Line(number) = exit {
+                    leaders.extend(target(lines, *number));
+                }
+            }
+            if exits.iter().any(|(exit, _)| *exit != Exit::Next) {
+                leaders.extend(code.get(position + 1));
+            }
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut block_of = vec![0; lines.len()];
+        for index in code {
+            if leaders.contains(&index) {
+                blocks.push(Block::default());
+            }
+            block_of[index] = blocks.len() - 1;
+            blocks.last_mut().unwrap().lines.push(index);
+        }
+
+        let end = blocks.len();
+        blocks.push(Block::default());
+
+        for (id, block) in blocks.iter_mut().enumerate().take(end) {
+            let last = *block.lines.last().unwrap();
+
+            for (exit, cond) in exits(lines[last].stmt()) {
+                // blocks are in the order of the lines, the end comes after the last one
+                let to = match exit {
+                    Exit::Next => id + 1,
+                    Exit::Line(number) => target(lines, number).map_or(end, |i| block_of[i]),
+                    Exit::End => end,
+                };
+                add_edge(block, to, cond);
+            }
+        }
+
+        Self { lines, blocks }
+    }
+
+    /// The empty block standing for the end of the program
+    pub fn end(&self) -> BlockId {
+        self.blocks.len() - 1
+    }
+
+    /// The block a `GOTO` to the given line jumps to
+    pub fn target(&self, number: u32) -> BlockId {
+        target(self.lines, number).map_or(self.end(), |index| self.block_of(index))
+    }
+
+    /// The block containing a line, given by its index in [`SyntaxTree::lines`]
+    ///
+    /// Empty lines belong to no block, the next block is returned for them.
+    pub fn block_of(&self, index: usize) -> BlockId {
+        self.blocks
+            .iter()
+            .position(|block| block.lines.last().is_some_and(|last| *last >= index))
+            .unwrap_or(self.end())
+    }
+
+    /// The block a statement at the end of `block` goes to, if it always goes to the same one
+    ///
+    /// This is meant for the branches of an `IF`.
+    pub fn destination(&self, block: BlockId, stmt: &Stmt) -> Option<BlockId> {
+        match exits(stmt).as_slice() {
+            [(Exit::Next, _)] => Some(block + 1),
+            [(Exit::Line(number), _)] => Some(self.target(*number)),
+            [(Exit::End, _)] => Some(self.end()),
+            _ => None,
+        }
+    }
+
+    /// The blocks that can be executed right before each block
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                predecessors[edge.to].push(id);
+            }
+        }
+
+        predecessors
+    }
+
+    /// Computes which blocks dominate each block, until nothing changes
+    pub fn dominators(&self) -> Dominators {
+        let reached = self.reachable();
+        let predecessors = self.predecessors();
+        let len = self.blocks.len();
+
+        let mut dominators = vec![vec![true; len]; len];
+        dominators[0] = (0..len).map(|id| id == 0).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for id in (1..len).filter(|id| reached[*id]) {
+                let mut new = vec![true; len];
+                for predecessor in predecessors[id].iter().filter(|p| reached[**p]) {
+                    for (dominator, dominates) in new.iter_mut().enumerate() {
+                        *dominates &= dominators[*predecessor][dominator];
+                    }
+                }
+                new[id] = true;
+
+                if new != dominators[id] {
+                    dominators[id] = new;
+                    changed = true;
+                }
+            }
+        }
+
+        Dominators(dominators)
+    }
+
+    /// The natural loops of the program, outer loops before the loops they contain
+    ///
+    /// Loops sharing a header are merged into one.
+    pub fn loops(&self) -> Vec<Loop> {
+        let reached = self.reachable();
+        let dominators = self.dominators();
+        let predecessors = self.predecessors();
+        let mut loops: Vec<Loop> = Vec::new();
+
+        for (latch, block) in self.blocks.iter().enumerate() {
+            for header in block.successors.iter().map(|edge| edge.to) {
+                // a back edge goes to a block which was necessarily executed before
+                if !reached[latch] || !dominators.dominates(header, latch) {
+                    continue;
+                }
+
+                let index = match loops.iter().position(|l| l.header == header) {
+                    Some(index) => index,
+                    None => {
+                        loops.push(Loop {
+                            header,
+                            blocks: vec![header],
+                            latches: Vec::new(),
+                        });
+                        loops.len() - 1
+                    }
+                };
+                let current = &mut loops[index];
+                current.latches.push(latch);
+
+                // everything reaching the latch without going through the header
+                let mut pending = vec![latch];
+                while let Some(id) = pending.pop() {
+                    if !current.blocks.contains(&id) {
+                        current.blocks.push(id);
+                        pending.extend(predecessors[id].iter().filter(|p| reached[**p]));
+                    }
+                }
+                current.blocks.sort_unstable();
+            }
+        }
+
+        loops.sort_by_key(|l| std::cmp::Reverse(l.blocks.len()));
+        loops
+    }
+
+    /// The lines of a block, with their index in [`SyntaxTree::lines`]
+    pub fn lines(&self, block: BlockId) -> impl Iterator<Item = (usize, &'a Line)> + '_ {
+        self.blocks[block]
+            .lines
+            .iter()
+            .map(|index| (*index, &self.lines[*index]))
+    }
+
+    /// Whether each block can be executed, starting from the first one
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+
+        while let Some(id) = pending.pop() {
+            if reached[id] {
+                continue;
+            }
+            reached[id] = true;
+            pending.extend(self.blocks[id].successors.iter().map(|edge| edge.to));
+        }
+
+        reached
+    }
+}
+
+/// Adds an edge to a block, merging it with the existing edge to the same block if any
+fn add_edge(block: &mut Block, to: BlockId, cond: Option<String>) {
+    let Some(edge) = block.successors.iter_mut().find(|edge| edge.to == to) else {
+        block.successors.push(Edge { to, cond });
+        return;
+    };
+
+    edge.cond = match (edge.cond.take(), cond) {
+        (Some(first), Some(second)) if first != second => Some(format!("{first} or {second}")),
+        (Some(first), Some(_)) => Some(first),
+        _ => None,
+    };
+}
+
+/// Where the execution can go after a statement, with the condition to go there
+fn exits(stmt: &Stmt) -> Vec<(Exit, Option<String>)> {
+    match &stmt.kind {
+        StmtKind::End => vec![(Exit::End, None)],
+        StmtKind::Goto(line) => vec![(Exit::Line(*line), None)],
+        StmtKind::If { cond, then, els } => {
+            let mut all = nested(exits(then), &cond.to_string());
+            match els {
+                Some(els) => all.extend(nested(exits(els), "else")),
+                None => all.push((Exit::Next, Some("else".to_string()))),
+            }
+
+            // both branches going to the same place, e.g. `IF A < 0 THEN PRINT A`
+            match all.first() {
+                Some((first, _)) if all.iter().all(|(exit, _)| exit == first) => {
+                    vec![(*first, None)]
+                }
+                _ => all,
+            }
+        }
+        StmtKind::Input(_) | StmtKind::Let(..) | StmtKind::Print(_) | StmtKind::NoOp => {
+            vec![(Exit::Next, None)]
+        }
+    }
+}
+
+/// Adds the condition of a branch of an `IF` to the exits of the branch
+fn nested(exits: Vec<(Exit, Option<String>)>, branch: &str) -> Vec<(Exit, Option<String>)> {
+    exits
+        .into_iter()
+        .map(|(exit, cond)| {
+            let cond = match cond {
+                Some(cond) => format!("{branch} and {cond}"),
+                None => branch.to_string(),
+            };
+            (exit, Some(cond))
+        })
+        .collect()
+}
+
+/// The index of the line a `GOTO` jumps to
+///
+/// Targets without a line, removed by optimizations for instance, go on to the next line.
+fn target(lines: &[Line], number: u32) -> Option<usize> {
+    lines
+        .iter()
+        .position(|line| !line.is_empty() && line.number() >= number as usize)
+}
+
+/// Draws each block with its lines, and the edges with their condition
+impl From<&Cfg<'_>> for Digraph {
+    fn from(cfg: &Cfg<'_>) -> Self {
+        let mut digraph = Digraph::default();
+
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            let node = if id == cfg.end() {
+                Node::new("end")
+            } else {
+                let mut label = format!("bb{id}");
+                for index in &block.lines {
+                    let line = &cfg.lines[*index];
+                    label += &format!("\n{} {}", line.number(), line.stmt());
+                }
+                let first = cfg.lines[block.lines[0]].stmt();
+                Node::new(&label)
+                    .tooltip(&first.span.to_string())
+                    .shape("box")
+            };
+
+            // the blocks are added in order, their index is their id
+            digraph.add_node(node);
+        }
+
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            for edge in &block.successors {
+                digraph.add_edge(id, edge.to, edge.cond.as_deref());
+            }
+        }
+
+        digraph
+    }
+}
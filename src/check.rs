@@ -0,0 +1,75 @@
+//! Semantic analysis, run between parsing and code generation.
+
+use std::collections::HashSet;
+
+use crate::{
+    error::{Diagnostic, SyntaxError, Warning},
+    span::Span,
+    symbol_table::SymbolTable,
+    syntax_tree::SyntaxTree,
+};
+
+/// This trait represents elements that can be semantically checked
+///
+/// Errors and warnings are accumulated in the [`Checker`] so that every one of them is reported at
+/// once.
+pub trait Check {
+    fn check(&self, checker: &mut Checker);
+}
+
+/// State of the semantic analysis
+pub struct Checker<'a> {
+    pub symbol_table: &'a SymbolTable,
+    /// Every line number of the program, used to validate jumps
+    pub lines: HashSet<usize>,
+    /// Every variable that is read
+    pub reads: HashSet<String>,
+    pub errors: Vec<SyntaxError>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Checker<'_> {
+    /// Reads the given variable, reporting an error if it is never declared
+    pub fn check_variable(&mut self, name: &str, span: Span) {
+        self.reads.insert(name.into());
+
+        if self.symbol_table.get(name).is_none() {
+            self.errors.push(SyntaxError::UndefinedVariable {
+                name: name.into(),
+                span,
+            });
+        }
+    }
+}
+
+/// Checks the given syntax tree, returning every error and warning found in source order
+pub fn check(ast: &SyntaxTree, symbol_table: &SymbolTable) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        symbol_table,
+        lines: HashSet::new(),
+        reads: HashSet::new(),
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    ast.check(&mut checker);
+
+    for (name, symbol) in symbol_table.iter() {
+        if !checker.reads.contains(name) {
+            checker.warnings.push(Warning::UnusedVariable {
+                name: name.clone(),
+                span: symbol.span(),
+            });
+        }
+    }
+
+    let mut diagnostics: Vec<Diagnostic> = checker
+        .errors
+        .iter()
+        .map(Diagnostic::from)
+        .chain(checker.warnings.iter().map(Diagnostic::from))
+        .collect();
+    diagnostics.sort_by_key(|d| d.span.start);
+
+    diagnostics
+}
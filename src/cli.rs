@@ -1,14 +1,244 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
 
-use argh::FromArgs;
+use argh::{EarlyExit, FromArgs};
+use tbc::optimize::{OptLevel, Passes};
 
 /// A tinyBASIC compiler
 #[derive(FromArgs)]
+#[argh(
+    note = "Run `{command_name} test <dir>` to check the programs of a directory against their \
+               expected output, a file named `test` is compiled with `{command_name} ./test`."
+)]
 pub struct Cli {
-    /// the file to compile
+    /// the file to compile, starts an interactive session if missing
     #[argh(positional)]
-    pub file: PathBuf,
+    pub file: Option<PathBuf>,
     /// run the program if compiled successfully
     #[argh(switch, short = 'r')]
     pub run: bool,
+    /// run the program with the interpreter instead of compiling it
+    #[argh(switch)]
+    pub interpret: bool,
+    /// check that the interpreter, the unoptimized and the optimized executables behave the same
+    /// on the standard input
+    #[argh(switch)]
+    pub verify_opt: bool,
+    /// how to print errors and warnings: human (default) or json
+    #[argh(option, default = "ErrorFormat::Human")]
+    pub error_format: ErrorFormat,
+    /// where to write the executable, defaults to dump.elf in the output directory
+    #[argh(option, short = 'o')]
+    pub output: Option<PathBuf>,
+    /// directory where to write the files, defaults to the current one
+    #[argh(option, default = "PathBuf::from(\".\")")]
+    pub out_dir: PathBuf,
+    /// comma separated files to produce: elf (default), ir, asm, ast, ost, cfg, parse-tree,
+    /// unoptimized (both udump.elf and udump.asm)
+    #[argh(option, default = "Emit::default()")]
+    pub emit: Emit,
+    /// optimization level: 0, 1 or 2 (default)
+    #[argh(option, short = 'O', default = "OptLevel::O2")]
+    pub opt_level: OptLevel,
+    /// comma separated passes to run instead of the ones of the optimization level
+    #[argh(option)]
+    pub passes: Option<Passes>,
+    /// print what each optimization pass changed, and the size of the program before and after
+    #[argh(switch)]
+    pub opt_report: bool,
+}
+
+impl Cli {
+    /// The passes selected by `--passes`, or by the optimization level
+    pub fn passes(&mut self) -> Passes {
+        let mut passes = self
+            .passes
+            .take()
+            .unwrap_or_else(|| Passes::level(self.opt_level));
+
+        if self.opt_report {
+            passes.enable_report();
+        }
+        passes
+    }
+}
+
+/// Runs Tiny BASIC programs and checks their output against their annotations
+#[derive(FromArgs)]
+pub struct TestCli {
+    /// a directory containing .tb files, or a single file
+    #[argh(positional)]
+    pub path: PathBuf,
+    /// run the programs with the interpreter instead of compiling them
+    #[argh(switch)]
+    pub interpret: bool,
+}
+
+/// What to do, depending on the command line arguments
+pub enum Command {
+    Compile(Cli),
+    Test(TestCli),
+}
+
+impl Command {
+    /// Parses the command line arguments
+    ///
+    /// `test` as the first argument selects [`TestCli`], which means a file named `test` must be
+    /// compiled as `./test`.
+    pub fn from_env() -> Self {
+        let args: Vec<String> = env::args().collect();
+        let command = Path::new(&args[0])
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&args[0]);
+
+        match args.get(1).map(String::as_str) {
+            Some("test") => Self::Test(parse(&[command, "test"], &args[2..])),
+            _ => Self::Compile(parse(&[command], &args[1..])),
+        }
+    }
+}
+
+/// Parses arguments with [`argh`], exiting on errors and `--help` like [`argh::from_env`]
+///
+/// Unlike [`argh::from_env`], this also accepts the `--option=value` and `-O2` syntaxes.
+fn parse<T: FromArgs>(command: &[&str], args: &[String]) -> T {
+    let args = normalize(args);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match T::from_args(command, &args) {
+        Ok(cli) => cli,
+        Err(EarlyExit { output, status }) => match status {
+            Ok(()) => {
+                println!("{output}");
+                process::exit(0);
+            }
+            Err(()) => {
+                eprintln!(
+                    "{output}\nRun {} --help for more information.",
+                    command.join(" ")
+                );
+                process::exit(1);
+            }
+        },
+    }
+}
+
+/// Splits `--option=value` and `-O2` arguments in two so that [`argh`] understands them
+fn normalize(args: &[String]) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                vec![option.to_string(), value.to_string()]
+            }
+            _ if arg.starts_with("-O") && arg.len() > 2 => {
+                vec!["-O".to_string(), arg[2..].to_string()]
+            }
+            _ => vec![arg.clone()],
+        })
+        .collect()
+}
+
+/// Output format of the diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Rendered with the source on stderr
+    Human,
+    /// One JSON object per line on stdout
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            s => Err(format!(
+                "unknown error format `{s}`, expected `human` or `json`"
+            )),
+        }
+    }
+}
+
+/// A file that can be produced by the compiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    /// The optimized executable
+    Elf,
+    /// The optimized intermediate representation
+    Ir,
+    /// The optimized assembly
+    Asm,
+    /// The syntax tree as a graph
+    Ast,
+    /// The optimized syntax tree as a graph
+    Ost,
+    /// The control-flow graph of the optimized syntax tree
+    Cfg,
+    /// The parse tree as a graph
+    ParseTree,
+    /// The executable and assembly without optimizations
+    Unoptimized,
+}
+
+impl FromStr for Artifact {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "elf" => Ok(Self::Elf),
+            "ir" => Ok(Self::Ir),
+            "asm" => Ok(Self::Asm),
+            "ast" => Ok(Self::Ast),
+            "ost" => Ok(Self::Ost),
+            "cfg" => Ok(Self::Cfg),
+            "parse-tree" => Ok(Self::ParseTree),
+            "unoptimized" => Ok(Self::Unoptimized),
+            s => Err(format!(
+                "unknown artifact `{s}`, expected `elf`, `ir`, `asm`, `ast`, `ost`, `cfg`, `parse-tree` or `unoptimized`"
+            )),
+        }
+    }
+}
+
+/// The set of files to produce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emit(Vec<Artifact>);
+
+impl Emit {
+    pub fn contains(&self, artifact: Artifact) -> bool {
+        self.0.contains(&artifact)
+    }
+
+    pub fn insert(&mut self, artifact: Artifact) {
+        if !self.contains(artifact) {
+            self.0.push(artifact);
+        }
+    }
+}
+
+impl Default for Emit {
+    fn default() -> Self {
+        Self(vec![Artifact::Elf])
+    }
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut emit = Self(Vec::new());
+
+        for artifact in s.split(',').filter(|a| !a.is_empty()) {
+            emit.insert(artifact.trim().parse()?);
+        }
+
+        Ok(emit)
+    }
 }
@@ -1,2 +1,10 @@
+mod compile_error;
+mod diagnostic;
+mod runtime_error;
 mod syntax_error;
+mod warning;
+pub use compile_error::*;
+pub use diagnostic::*;
+pub use runtime_error::*;
 pub use syntax_error::*;
+pub use warning::*;
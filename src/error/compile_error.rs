@@ -0,0 +1,36 @@
+use std::{error::Error, fmt::Display};
+
+use super::Diagnostic;
+
+/// The reasons a program cannot be compiled
+#[derive(Debug)]
+pub enum CompileError {
+    /// The program does not follow the grammar or has wrong line numbers
+    Syntax(Vec<Diagnostic>),
+    /// The program is syntactically valid but meaningless, e.g. it reads an undefined variable
+    ///
+    /// This also contains the warnings, so they can be shown in order with the errors.
+    Semantic(Vec<Diagnostic>),
+}
+
+impl CompileError {
+    /// Every diagnostic explaining this error
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            CompileError::Syntax(diagnostics) | CompileError::Semantic(diagnostics) => diagnostics,
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors = self.diagnostics().iter().filter(|d| d.is_error()).count();
+
+        match self {
+            CompileError::Syntax(_) => write!(f, "{errors} syntax error(s)"),
+            CompileError::Semantic(_) => write!(f, "{errors} semantic error(s)"),
+        }
+    }
+}
+
+impl Error for CompileError {}
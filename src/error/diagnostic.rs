@@ -0,0 +1,214 @@
+use std::fmt::Display;
+
+use pest::error::{Error, InputLocation, LineColLocation};
+
+use super::{RuntimeError, SyntaxError, Warning};
+use crate::{
+    parser::{renamed_rules, Rule},
+    span::Span,
+};
+
+/// How bad a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message pointing at the source code
+///
+/// Every error and warning of the compiler is converted to this before being shown to the user,
+/// so they all share the same format.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Identifies the kind of diagnostic, e.g. `E0004`
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            help: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+
+    /// Adds a help note with a fluent pattern
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders this diagnostic with the offending source line
+    ///
+    /// ```text
+    /// error[E0004]: undefined variable `a`
+    ///  --> data/file.tb:3:7
+    ///   |
+    /// 3 | PRINT a
+    ///   |       ^
+    ///   |
+    ///   = help: assign it first with `LET a = ...` or `INPUT a`
+    /// ```
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let line = source.lines().nth(self.span.line - 1).unwrap_or_default();
+        let number = self.span.line.to_string();
+        let pad = " ".repeat(number.len());
+
+        // keep tabs so that the underline is aligned with the source
+        let indent: String = line
+            .chars()
+            .take(self.span.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // only underline the first line of the span
+        let length = source
+            .get(self.span.start..self.span.end)
+            .and_then(|text| text.lines().next())
+            .map_or(0, |text| text.trim_end().chars().count())
+            .max(1);
+
+        let mut rendered = format!(
+            "{}[{}]: {}\n{pad}--> {path}:{}\n{pad} |\n{number} | {line}\n{pad} | {indent}{}\n",
+            self.severity,
+            self.code,
+            self.message,
+            self.span,
+            "^".repeat(length),
+        );
+
+        if let Some(help) = &self.help {
+            rendered += &format!("{pad} |\n{pad} = help: {help}\n");
+        }
+
+        rendered
+    }
+
+    /// Renders this diagnostic as a single line JSON object
+    ///
+    /// ```json
+    /// {"file":"data/file.tb","span":{"start":6,"end":7,"line":3,"col":7},"severity":"error","code":"E0004","message":"undefined variable `a`","help":"..."}
+    /// ```
+    pub fn to_json(&self, path: &str) -> String {
+        format!(
+            r#"{{"file":{},"span":{{"start":{},"end":{},"line":{},"col":{}}},"severity":"{}","code":"{}","message":{},"help":{}}}"#,
+            json_string(path),
+            self.span.start,
+            self.span.end,
+            self.span.line,
+            self.span.col,
+            self.severity,
+            self.code,
+            json_string(&self.message),
+            self.help.as_deref().map_or("null".into(), json_string),
+        )
+    }
+}
+
+/// Quotes and escapes a string for JSON
+fn json_string(str: &str) -> String {
+    let mut json = String::from('"');
+
+    for c in str.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+impl From<&SyntaxError> for Diagnostic {
+    fn from(value: &SyntaxError) -> Self {
+        let diagnostic = Diagnostic::error(value.code(), value.to_string(), value.span());
+
+        match value.help() {
+            Some(help) => diagnostic.with_help(help),
+            None => diagnostic,
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(value: &Warning) -> Self {
+        let diagnostic = Diagnostic::warning(value.code(), value.to_string(), value.span());
+
+        match value.help() {
+            Some(help) => diagnostic.with_help(help),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Errors without a span, like I/O errors, point at the beginning of the file
+impl From<&RuntimeError> for Diagnostic {
+    fn from(value: &RuntimeError) -> Self {
+        let span = value.span().unwrap_or(Span {
+            line: 1,
+            col: 1,
+            ..Default::default()
+        });
+
+        Diagnostic::error(value.code(), value.to_string(), span)
+    }
+}
+
+impl From<Error<Rule>> for Diagnostic {
+    fn from(value: Error<Rule>) -> Self {
+        let value = value.renamed_rules(renamed_rules);
+
+        let (start, end) = match value.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let (line, col) = match value.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(pos, _) => pos,
+        };
+
+        Diagnostic::error(
+            "E0001",
+            value.variant.message(),
+            Span {
+                start,
+                end,
+                line,
+                col,
+            },
+        )
+    }
+}
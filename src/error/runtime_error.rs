@@ -0,0 +1,53 @@
+use std::{error::Error, fmt::Display, io};
+
+use crate::span::Span;
+
+/// The different types of error that can occur while interpreting a program
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The result of an operation does not fit in 32 bits
+    Overflow(Span),
+    /// A division by a value that is zero at runtime
+    DivisionByZero(Span),
+    /// Reading the input or writing the output failed
+    Io(io::Error),
+}
+
+impl RuntimeError {
+    /// A unique code identifying this kind of error
+    ///
+    /// It is shared with the equivalent [`SyntaxError`](super::SyntaxError) if any.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::Overflow(_) => "E0007",
+            RuntimeError::DivisionByZero(_) => "E0006",
+            RuntimeError::Io(_) => "E0008",
+        }
+    }
+
+    /// Where the error is in the source, if it comes from it
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RuntimeError::Overflow(span) | RuntimeError::DivisionByZero(span) => Some(*span),
+            RuntimeError::Io(_) => None,
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::Overflow(_) => write!(f, "arithmetic overflow"),
+            RuntimeError::DivisionByZero(_) => write!(f, "division by zero"),
+            RuntimeError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+impl From<io::Error> for RuntimeError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
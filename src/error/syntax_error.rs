@@ -1,25 +1,86 @@
 use std::{error::Error, fmt::Display, num::ParseIntError};
 
+use crate::span::Span;
+
 /// The different types of syntax error that can occur
 #[derive(Debug)]
 pub enum SyntaxError {
-    WrongLineNumber(usize),
-    ParseIntError(ParseIntError),
+    /// A line number is not greater than the previous one
+    WrongLineNumber {
+        number: usize,
+        previous: usize,
+        span: Span,
+    },
+    ParseIntError(ParseIntError, Span),
+    /// A variable is used but never assigned with `LET` or `INPUT`
+    UndefinedVariable {
+        name: String,
+        span: Span,
+    },
+    /// A `GOTO` or `GOSUB` targets a line that does not exist
+    UndefinedLine {
+        line: u32,
+        span: Span,
+    },
+    /// A division by a constant expression evaluating to zero
+    DivisionByZero(Span),
+}
+
+impl SyntaxError {
+    /// A unique code identifying this kind of error
+    ///
+    /// `E0001` is reserved for grammar errors reported by [`pest`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            SyntaxError::WrongLineNumber { .. } => "E0002",
+            SyntaxError::ParseIntError(..) => "E0003",
+            SyntaxError::UndefinedVariable { .. } => "E0004",
+            SyntaxError::UndefinedLine { .. } => "E0005",
+            SyntaxError::DivisionByZero(_) => "E0006",
+        }
+    }
+
+    /// Where the error is in the source
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxError::WrongLineNumber { span, .. }
+            | SyntaxError::ParseIntError(_, span)
+            | SyntaxError::UndefinedVariable { span, .. }
+            | SyntaxError::UndefinedLine { span, .. }
+            | SyntaxError::DivisionByZero(span) => *span,
+        }
+    }
+
+    /// A hint on how to fix the error
+    pub fn help(&self) -> Option<String> {
+        match self {
+            SyntaxError::WrongLineNumber { previous, .. } => Some(format!(
+                "line numbers must be strictly increasing, the previous line is {previous}"
+            )),
+            SyntaxError::ParseIntError(..) => None,
+            SyntaxError::UndefinedVariable { name, .. } => Some(format!(
+                "assign it first with `LET {name} = ...` or `INPUT {name}`"
+            )),
+            SyntaxError::UndefinedLine { line, .. } => {
+                Some(format!("there is no line numbered {line} in this program"))
+            }
+            SyntaxError::DivisionByZero(_) => Some("the divisor always evaluates to 0".into()),
+        }
+    }
 }
 
 impl Display for SyntaxError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SyntaxError::WrongLineNumber(line) => write!(f, "Wrong line number at line {line}"),
-            SyntaxError::ParseIntError(e) => e.fmt(f),
+            SyntaxError::WrongLineNumber { number, .. } => write!(f, "wrong line number {number}"),
+            SyntaxError::ParseIntError(e, _) => e.fmt(f),
+            SyntaxError::UndefinedVariable { name, .. } => {
+                write!(f, "undefined variable `{name}`")
+            }
+            SyntaxError::UndefinedLine { line, .. } => write!(f, "jump to undefined line {line}"),
+            SyntaxError::DivisionByZero(_) => write!(f, "division by zero"),
         }
     }
 }
 
 impl Error for SyntaxError {}
-
-impl From<ParseIntError> for SyntaxError {
-    fn from(value: ParseIntError) -> Self {
-        SyntaxError::ParseIntError(value)
-    }
-}
@@ -0,0 +1,43 @@
+use std::fmt::Display;
+
+use crate::span::Span;
+
+/// The different types of warning, they do not prevent compilation
+#[derive(Debug)]
+pub enum Warning {
+    /// A variable is assigned but never read
+    UnusedVariable { name: String, span: Span },
+}
+
+impl Warning {
+    /// A unique code identifying this kind of warning
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable { .. } => "W0001",
+        }
+    }
+
+    /// Where the warning is in the source
+    pub fn span(&self) -> Span {
+        match self {
+            Warning::UnusedVariable { span, .. } => *span,
+        }
+    }
+
+    /// A hint on how to fix the warning
+    pub fn help(&self) -> Option<String> {
+        match self {
+            Warning::UnusedVariable { .. } => {
+                Some("remove it, or read it in an expression or a condition".into())
+            }
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::UnusedVariable { name, .. } => write!(f, "unused variable `{name}`"),
+        }
+    }
+}
@@ -1,81 +1,155 @@
-use std::{fs::File, io::Write, process::Command};
+use std::collections::HashMap;
 
 use tiny_elf::{
-    asm::{AsAsm, Program},
+    asm::{Operand, Program, Register},
     bytes::AsBytes,
     program_header::Flags,
     Elf,
 };
 
 use crate::{
-    graphviz::{compile_dot, Digraph, ToNode},
-    optimize::Optimize,
+    ir::{Temp, TEMP_REGISTERS},
+    optimize::Passes,
     symbol_table::SymbolTable,
     syntax_tree::SyntaxTree,
 };
 
-pub const PARSE_TREE_DOT_FILE: &str = "parse_tree.dot";
+/// This trait represents elements that can be converted to assembly for code generation
+pub trait Generate {
+    fn generate(&self, program: Program, ctx: &mut GenContext) -> Program;
+}
 
-pub const OPTIMIZED: Names = Names {
-    ast: "ost.dot",
-    asm: "dump.asm",
-    bin: "dump.elf",
-};
+/// State of the code generation
+pub struct GenContext<'a> {
+    pub symbol_table: &'a SymbolTable,
+    /// Variables kept in a register, the others are on the stack
+    pub registers: HashMap<String, Register>,
+    /// Where the temporaries of the expression being generated are
+    pub temps: Temps,
+}
 
-pub const UNOPTIMIZED: Names = Names {
-    ast: "ast.dot",
-    asm: "udump.asm",
-    bin: "udump.elf",
-};
+impl<'a> GenContext<'a> {
+    pub fn new(symbol_table: &'a SymbolTable) -> Self {
+        Self {
+            symbol_table,
+            registers: HashMap::new(),
+            temps: Temps::default(),
+        }
+    }
 
-/// This trait represents elements that can be converted to assembly for code generation
-pub trait Generate {
-    fn generate(&self, program: Program, symbol_table: &mut SymbolTable) -> Program;
+    /// Puts the variable with the given name in a register
+    pub fn read(&self, name: &str, register: Register, program: Program) -> Program {
+        use tiny_elf::asm::{Mnemonic::*, Register::*};
+
+        match self.registers.get(name) {
+            Some(variable) => program.add(Mov(register, (*variable).into())),
+            None if register == Rbx => self.symbol_table.access(name, program),
+            None => self
+                .symbol_table
+                .access(name, program)
+                .add(Mov(register, Rbx.into())),
+        }
+    }
+
+    /// Puts an operand in the variable with the given name
+    pub fn write(&self, name: &str, value: Operand, program: Program) -> Program {
+        use tiny_elf::asm::Mnemonic::*;
+
+        match self.registers.get(name) {
+            Some(variable) => program.add(Mov(*variable, value)),
+            None => self.symbol_table.write(name, value, program),
+        }
+    }
 }
 
-pub struct Names<'a> {
-    pub ast: &'a str,
-    pub asm: &'a str,
-    pub bin: &'a str,
+/// Locations of the temporaries, in registers or on the stack
+///
+/// Temporaries are read once, in the reverse order they are written as long as expressions are
+/// generated one at a time, so those on the stack are always popped from the top.
+pub struct Temps {
+    /// Registers not holding a temporary, the next one to use last
+    free: Vec<Register>,
+    /// Temporaries in a register
+    registers: HashMap<Temp, Register>,
+    /// Temporaries on the stack, the last one on top
+    stack: Vec<Temp>,
 }
 
-pub fn generate(
-    ast: &SyntaxTree,
-    symbol_table: &mut SymbolTable,
-    names: &Names,
-    optimize: bool,
-) -> std::io::Result<()> {
-    // Write ast
-    {
-        let mut file = File::create(names.ast)?;
-        file.write_all(Digraph::new(ast.to_node()).to_string().as_bytes())?;
+impl Temps {
+    /// A free register, if any
+    pub fn free(&self) -> Option<Register> {
+        self.free.last().copied()
     }
 
-    // generate assembly program
-    let mut program = ast.generate(Program::default(), symbol_table);
-    if optimize {
-        program = program.optimize();
+    /// Puts a temporary in a free register
+    pub fn assign(&mut self, temp: Temp, register: Register) {
+        self.free.retain(|r| *r != register);
+        self.registers.insert(temp, register);
     }
-    {
-        let mut file = File::create(names.asm)?;
-        file.write_all(program.as_asm().as_bytes())?;
+
+    /// Records that a temporary was pushed on the stack
+    pub fn push(&mut self, temp: Temp) {
+        self.stack.push(temp);
     }
 
-    // generate binary from assembly
-    let mut elf = Elf::new(program.clone());
-    elf.add_data(program.data(), Flags::all());
-    elf.backpatch();
-    {
-        let mut file = File::create(names.bin)?;
-        file.write_all(&elf.as_bytes())?;
+    /// The register holding a temporary, if it is not on the stack
+    pub fn register(&self, temp: Temp) -> Option<Register> {
+        self.registers.get(&temp).copied()
+    }
+
+    pub fn is_on_stack(&self, temp: Temp) -> bool {
+        self.stack.contains(&temp)
     }
 
-    if let Err(e) = compile_dot(names.ast) {
-        eprintln!("{e}");
-        eprintln!("Graphviz might not be installed. See https://graphviz.org/download/");
-    };
+    pub fn is_on_top(&self, temp: Temp) -> bool {
+        self.stack.last() == Some(&temp)
+    }
 
-    Command::new("chmod").arg("u+x").arg(names.bin).status()?;
+    /// Forgets a temporary once it is read, returning its register
+    ///
+    /// [`None`] means it was on the stack and must be popped.
+    pub fn release(&mut self, temp: Temp) -> Option<Register> {
+        match self.registers.remove(&temp) {
+            Some(register) => {
+                self.free.push(register);
+                Some(register)
+            }
+            None => {
+                debug_assert!(self.is_on_top(temp), "{temp} is not on top of the stack");
+                self.stack.pop();
+                None
+            }
+        }
+    }
+}
 
-    Ok(())
+impl Default for Temps {
+    fn default() -> Self {
+        Self {
+            free: TEMP_REGISTERS.into_iter().rev().collect(),
+            registers: HashMap::new(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+/// Converts a syntax tree to an assembly program, through its [intermediate
+/// representation](crate::lower)
+///
+/// Only the variables still used by the syntax tree get space on the stack. The assembly itself is
+/// then optimized with the passes on [`Program`].
+pub fn assemble(ast: &SyntaxTree, symbol_table: &SymbolTable, passes: &Passes) -> Program {
+    let ir = crate::lower(ast, passes);
+    let symbol_table = symbol_table.retain(&ast.variables());
+    let mut program = ir.generate(Program::default(), &mut GenContext::new(&symbol_table));
+    passes.run_program(&mut program);
+    program
+}
+
+/// Links an assembly program into the bytes of an ELF executable
+pub fn link(program: &Program) -> Vec<u8> {
+    let mut elf = Elf::new(program.clone());
+    elf.add_data(program.data(), Flags::all());
+    elf.backpatch();
+    elf.as_bytes()
 }
@@ -1,21 +1,86 @@
 use std::fmt::Display;
 
-use super::Node;
+use super::{escape, Node};
 
 /// A graphviz directed graph
+///
+/// Nodes are identified by the order they were added in. A tree is added from its root, with an
+/// edge from each node to each of its children.
+#[derive(Default)]
 pub struct Digraph {
-    root: Node,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+/// An edge between two nodes of a [`Digraph`]
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
 }
 
 impl Digraph {
     /// Creates a new directed graph from a root node.
     pub fn new(root: Node) -> Self {
-        Self { root }
+        let mut digraph = Self::default();
+        digraph.add_tree(root);
+        digraph
+    }
+
+    /// Adds a node without its children, returning its index
+    pub fn add_node(&mut self, mut node: Node) -> usize {
+        node.children.clear();
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Adds a node and its children, returning the index of the node
+    pub fn add_tree(&mut self, mut node: Node) -> usize {
+        let children = std::mem::take(&mut node.children);
+        let index = self.add_node(node);
+
+        for child in children {
+            let child = self.add_tree(child);
+            self.add_edge(index, child, None);
+        }
+
+        index
+    }
+
+    /// Adds an edge between two nodes, given by their index
+    pub fn add_edge(&mut self, from: usize, to: usize, label: Option<&str>) {
+        self.edges.push(Edge {
+            from,
+            to,
+            label: label.map(escape),
+        });
+    }
+}
+
+impl From<Node> for Digraph {
+    fn from(root: Node) -> Self {
+        Self::new(root)
     }
 }
 
 impl Display for Digraph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "digraph {{\n{}}}", self.root)
+        writeln!(f, "digraph {{")?;
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            writeln!(f, "node{index} [{}];", node.attributes())?;
+        }
+        for edge in &self.edges {
+            match &edge.label {
+                Some(label) => writeln!(
+                    f,
+                    "node{} -> node{} [label=\"{label}\"];",
+                    edge.from, edge.to
+                )?,
+                None => writeln!(f, "node{} -> node{};", edge.from, edge.to)?,
+            }
+        }
+
+        write!(f, "}}")
     }
 }
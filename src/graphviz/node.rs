@@ -1,46 +1,75 @@
-use std::fmt::Display;
-
-static mut NODE_COUNT: u32 = 0;
-
 // A basic graphviz node
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Node {
     label: String,
-    children: Vec<Node>,
+    tooltip: Option<String>,
+    shape: Option<&'static str>,
+    pub(super) children: Vec<Node>,
 }
 
 impl Node {
     pub fn new(label: &str) -> Self {
         Self {
-            label: label.escape_default().to_string(),
+            label: escape(label),
+            tooltip: None,
+            shape: None,
             children: Vec::new(),
         }
     }
 
+    /// Sets the text shown when hovering this node, e.g. its location in the source
+    pub fn tooltip(mut self, tooltip: &str) -> Self {
+        self.tooltip = Some(escape(tooltip));
+        self
+    }
+
+    /// Sets the shape of this node, e.g. `box`
+    pub fn shape(mut self, shape: &'static str) -> Self {
+        self.shape = Some(shape);
+        self
+    }
+
     /// Adds children to this node with a fluent pattern
+    // a builder method, not an addition
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, node: Node) -> Self {
         self.children.push(node);
         self
     }
 }
 
-impl Display for Node {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = format!("node{}", unsafe {
-            NODE_COUNT += 1;
-            NODE_COUNT
-        });
-        let mut node = format!("{} [label=\"{}\"];\n", name, self.label);
-
-        for (_, child) in self.children.iter().enumerate() {
-            // render link with child
-            let child_name = format!("node{}", unsafe { NODE_COUNT + 1 });
-            node += &format!("{} -> {};\n", name, child_name);
-
-            // render child
-            node += &child.to_string();
+impl Node {
+    /// The attributes of this node in the dot language, e.g. `label="if"`
+    pub(super) fn attributes(&self) -> String {
+        let mut attributes = format!("label=\"{}\"", self.label);
+
+        if let Some(tooltip) = &self.tooltip {
+            attributes += &format!(", tooltip=\"{tooltip}\"");
+        }
+        if let Some(shape) = &self.shape {
+            attributes += &format!(", shape={shape}");
         }
 
-        write!(f, "{}", node)
+        attributes
+    }
+}
+
+/// Escapes a label for graphviz
+///
+/// Unlike [`str::escape_default`], this keeps non-ASCII characters as is.
+pub(super) fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
     }
+
+    escaped
 }
@@ -0,0 +1,91 @@
+//! Executes a syntax tree directly, without generating any code.
+//!
+//! This is the reference behavior of the language, native executables should behave the same.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::{error::RuntimeError, syntax_tree::SyntaxTree};
+
+/// This trait represents elements that can be executed by the [`Interpreter`]
+pub trait Interpret {
+    type Output;
+
+    fn interpret<R: Read, W: Write>(
+        &self,
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<Self::Output, RuntimeError>;
+}
+
+/// What to execute after a statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// The next line
+    Next,
+    /// The line with the given number
+    Goto(u32),
+    /// Nothing, the program ends
+    End,
+}
+
+/// State of the execution
+pub struct Interpreter<R, W> {
+    /// Variables never assigned are 0
+    variables: HashMap<String, i32>,
+    input: R,
+    output: W,
+}
+
+impl<R: Read, W: Write> Interpreter<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            variables: HashMap::new(),
+            input,
+            output,
+        }
+    }
+
+    /// Runs a whole program
+    pub fn run(&mut self, ast: &SyntaxTree) -> Result<(), RuntimeError> {
+        let result = ast.interpret(self);
+        self.output.flush()?;
+        result
+    }
+
+    /// Forgets every variable, they are 0 again
+    pub fn clear(&mut self) {
+        self.variables.clear();
+    }
+
+    /// The input of the program, to read from it between statements
+    pub fn reader(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    pub fn variable(&self, name: &str) -> i32 {
+        self.variables.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: i32) {
+        self.variables.insert(name.into(), value);
+    }
+
+    /// Reads a variable from the input, like native executables do
+    ///
+    /// Up to 8 bytes are read over the variable as a little endian integer, then 48 is subtracted
+    /// so that a single digit gives its value.
+    pub fn input(&mut self, name: &str) -> Result<(), RuntimeError> {
+        let mut bytes = i64::from(self.variable(name)).to_le_bytes();
+        let _ = self.input.read(&mut bytes)?;
+
+        self.set_variable(name, (i64::from_le_bytes(bytes) - 48) as i32);
+        Ok(())
+    }
+
+    pub fn print(&mut self, str: &str) -> Result<(), RuntimeError> {
+        self.output.write_all(str.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! Intermediate representation between the [`SyntaxTree`](crate::syntax_tree::SyntaxTree) and
+//! the assembly [`Program`](tiny_elf::asm::Program).
+//!
+//! The program is a list of basic blocks of three-address instructions. Intermediate results of
+//! arithmetic expressions are held in temporaries, and each block ends with an explicit jump.
+
+use std::fmt::Display;
+
+use crate::{
+    span::Span,
+    syntax_tree::{ArOp, RelOp},
+};
+
+mod builder;
+mod codegen;
+
+pub use builder::*;
+pub use codegen::{is_source_label, TEMP_REGISTERS, VARIABLE_REGISTERS};
+
+/// Identifies a [`Block`], this is also its index in [`Ir::blocks`]
+pub type BlockId = usize;
+
+/// An intermediate result, assigned exactly once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Temp(pub usize);
+
+/// The operand of an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Const(i32),
+    Var(String),
+    Temp(Temp),
+}
+
+impl Value {
+    /// The name of the variable, if this is one
+    pub fn as_var(&self) -> Option<&str> {
+        match self {
+            Value::Var(var) => Some(var),
+            _ => None,
+        }
+    }
+}
+
+/// The functions of the runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Runtime {
+    /// Prints a number
+    PrintNumber(Value),
+    /// Prints the string at the given index of [`Ir::strings`]
+    PrintString(usize),
+    /// Reads a variable from the input
+    Input(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inst {
+    /// `dst = lhs op rhs`
+    Binary {
+        dst: Temp,
+        op: ArOp,
+        lhs: Value,
+        rhs: Value,
+    },
+    /// `var = value`
+    Store {
+        var: String,
+        value: Value,
+    },
+    Call(Runtime),
+    /// Marks where the code of a source line starts, it generates a label and no code
+    Source(Span),
+}
+
+impl Inst {
+    /// The values read by this instruction
+    pub fn operands(&self) -> Vec<&Value> {
+        match self {
+            Inst::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::Store { value, .. } | Inst::Call(Runtime::PrintNumber(value)) => vec![value],
+            Inst::Call(Runtime::PrintString(_) | Runtime::Input(_)) | Inst::Source(_) => vec![],
+        }
+    }
+
+    /// The variable written by this instruction, if any
+    pub fn written(&self) -> Option<&str> {
+        match self {
+            Inst::Store { var, .. } | Inst::Call(Runtime::Input(var)) => Some(var),
+            _ => None,
+        }
+    }
+}
+
+/// How a [`Block`] ends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Goes to `then` if `lhs relop rhs`, to `els` otherwise
+    Branch {
+        relop: RelOp,
+        lhs: Value,
+        rhs: Value,
+        then: BlockId,
+        els: BlockId,
+    },
+    /// Ends the program
+    Exit,
+}
+
+impl Terminator {
+    /// The values read by this terminator
+    pub fn operands(&self) -> Vec<&Value> {
+        match self {
+            Terminator::Branch { lhs, rhs, .. } => vec![lhs, rhs],
+            Terminator::Jump(_) | Terminator::Exit => vec![],
+        }
+    }
+
+    /// The blocks that can be executed after this one
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then, els, .. } => vec![*then, *els],
+            Terminator::Exit => vec![],
+        }
+    }
+}
+
+/// A sequence of instructions always executed from the start to the end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+    /// The number of the source line starting this block, if any
+    pub line: Option<usize>,
+}
+
+/// A whole program, starting at the first block
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Ir {
+    pub blocks: Vec<Block>,
+    /// String literals
+    pub strings: Vec<String>,
+    /// Variables kept in a register instead of on the stack, at most one per
+    /// [`VARIABLE_REGISTERS`]
+    pub registers: Vec<String>,
+}
+
+impl Display for Temp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Const(n) => write!(f, "{n}"),
+            Value::Var(name) => write!(f, "{name}"),
+            Value::Temp(temp) => write!(f, "{temp}"),
+        }
+    }
+}
+
+impl Display for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Runtime::PrintNumber(value) => write!(f, "printn {value}"),
+            Runtime::PrintString(index) => write!(f, "print @{index}"),
+            Runtime::Input(var) => write!(f, "input {var}"),
+        }
+    }
+}
+
+impl Display for Inst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inst::Binary { dst, op, lhs, rhs } => write!(f, "{dst} = {lhs} {op} {rhs}"),
+            Inst::Store { var, value } => write!(f, "{var} = {value}"),
+            Inst::Call(runtime) => write!(f, "call {runtime}"),
+            Inst::Source(span) => write!(f, "; line {span}"),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump bb{target}"),
+            Terminator::Branch {
+                relop,
+                lhs,
+                rhs,
+                then,
+                els,
+            } => write!(f, "branch {lhs} {relop} {rhs}, bb{then}, bb{els}"),
+            Terminator::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+/// Dumps the program in a textual form
+///
+/// ```text
+/// @0 = "lower\n"
+///
+/// bb0:
+///     ; line 3:1
+///     %0 = a + 1
+///     a = %0
+///     branch a < 10, bb1, bb2
+/// ```
+impl Display for Ir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(f, "@{index} = {string:?}")?;
+        }
+        if !self.registers.is_empty() {
+            writeln!(f, "; in registers: {}", self.registers.join(", "))?;
+        }
+        if !self.strings.is_empty() || !self.registers.is_empty() {
+            writeln!(f)?;
+        }
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            match block.line {
+                Some(line) => writeln!(f, "bb{id}: ; line {line}")?,
+                None => writeln!(f, "bb{id}:")?,
+            }
+            for inst in &block.insts {
+                writeln!(f, "    {inst}")?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use super::{Block, BlockId, Inst, Ir, Temp, Terminator};
+use crate::{span::Span, syntax_tree::SyntaxTree};
+
+/// This trait represents elements that can be converted to the intermediate representation
+pub trait Lower {
+    type Output;
+
+    fn lower(&self, builder: &mut Builder) -> Self::Output;
+}
+
+/// State of the conversion to the intermediate representation
+///
+/// Blocks are created as needed and placed in the order they are started, which is the order of
+/// the generated code.
+pub struct Builder {
+    /// Every block, by order of creation
+    blocks: Vec<Block>,
+    /// Creation order of the blocks, by order of placement
+    layout: Vec<BlockId>,
+    /// The block receiving instructions, [`None`] after a jump until a new block starts
+    current: Option<BlockId>,
+    temps: usize,
+    strings: Vec<String>,
+    /// Blocks starting at the target of a `GOTO`, by line number
+    lines: BTreeMap<usize, BlockId>,
+    /// The source line started after a jump, marked once it has code
+    source: Option<Span>,
+}
+
+impl Builder {
+    /// Creates a builder for a program jumping to the given line numbers
+    pub fn new(targets: impl IntoIterator<Item = usize>) -> Self {
+        let mut builder = Self {
+            blocks: Vec::new(),
+            layout: Vec::new(),
+            current: None,
+            temps: 0,
+            strings: Vec::new(),
+            lines: BTreeMap::new(),
+            source: None,
+        };
+
+        for line in targets {
+            let block = builder.new_block();
+            builder.blocks[block].line = Some(line);
+            builder.lines.insert(line, block);
+        }
+
+        builder
+    }
+
+    /// A new temporary
+    pub fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps - 1)
+    }
+
+    /// Adds a string literal, returning its index
+    pub fn string(&mut self, string: &str) -> usize {
+        self.strings.push(string.into());
+        self.strings.len() - 1
+    }
+
+    /// Creates a block, without starting it
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            insts: Vec::new(),
+            terminator: Terminator::Exit,
+            line: None,
+        });
+        self.blocks.len() - 1
+    }
+
+    /// The block starting at the given line
+    pub fn line_block(&self, line: usize) -> BlockId {
+        self.lines[&line]
+    }
+
+    /// Adds an instruction to the current block
+    ///
+    /// Instructions following a jump are unreachable, they are put in a new block anyway.
+    pub fn emit(&mut self, inst: Inst) {
+        let block = match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.start(block);
+                block
+            }
+        };
+
+        if let Some(span) = self.source.take() {
+            self.blocks[block].insts.push(Inst::Source(span));
+        }
+        self.blocks[block].insts.push(inst);
+    }
+
+    /// Ends the current block, if it is reachable
+    pub fn end(&mut self, terminator: Terminator) {
+        if let Some(block) = self.current.take() {
+            self.blocks[block].terminator = terminator;
+        }
+    }
+
+    /// Places a block after the current one, falling through to it
+    pub fn start(&mut self, block: BlockId) {
+        self.end(Terminator::Jump(block));
+        self.layout.push(block);
+        self.current = Some(block);
+    }
+
+    /// Starts a source line, in a new block if a `GOTO` targets it
+    ///
+    /// Targets without a line, removed by optimizations for instance, start before the next line.
+    /// The start of the line is marked with its location in the source, unless it is unreachable
+    /// and has no code.
+    pub fn start_line(&mut self, number: usize, span: Span) {
+        let targets: Vec<BlockId> = self.lines.range(..=number).map(|(_, b)| *b).collect();
+
+        for block in targets {
+            if !self.layout.contains(&block) {
+                self.start(block);
+            }
+        }
+
+        match self.current {
+            Some(block) => {
+                self.source = None;
+                self.blocks[block].insts.push(Inst::Source(span));
+            }
+            None => self.source = Some(span),
+        }
+    }
+
+    /// Ends the program, numbering blocks in the order they are placed
+    pub fn finish(mut self) -> Ir {
+        self.end(Terminator::Exit);
+
+        // targets after the last line
+        let targets: Vec<BlockId> = self.lines.values().copied().collect();
+        for block in targets {
+            if !self.layout.contains(&block) {
+                self.start(block);
+                self.end(Terminator::Exit);
+            }
+        }
+
+        let mut ids = vec![0; self.blocks.len()];
+        for (id, block) in self.layout.iter().enumerate() {
+            ids[*block] = id;
+        }
+
+        let mut blocks: Vec<Option<Block>> = self.blocks.into_iter().map(Some).collect();
+        let blocks = self
+            .layout
+            .iter()
+            .map(|block| {
+                let mut block = blocks[*block].take().unwrap();
+                match &mut block.terminator {
+                    Terminator::Jump(target) => *target = ids[*target],
+                    Terminator::Branch { then, els, .. } => {
+                        *then = ids[*then];
+                        *els = ids[*els];
+                    }
+                    Terminator::Exit => {}
+                }
+                block
+            })
+            .collect();
+
+        Ir {
+            blocks,
+            strings: self.strings,
+            registers: Vec::new(),
+        }
+    }
+}
+
+/// Converts a syntax tree to the intermediate representation
+pub fn lower(ast: &SyntaxTree) -> Ir {
+    let mut builder = Builder::new(ast.targets());
+    ast.lower(&mut builder);
+    builder.finish()
+}
@@ -0,0 +1,394 @@
+use std::collections::HashSet;
+
+use tiny_elf::asm::{
+    Immediate::Imm8, Memory, Mnemonic::*, Operand, Program, Register, Register::*,
+};
+
+use super::{BlockId, Inst, Ir, Runtime, Terminator, Value};
+use crate::{
+    generate::{GenContext, Generate},
+    span::Span,
+    syntax_tree::{ArOp, RelOp},
+};
+
+/// The registers that can hold variables, in order of priority
+///
+/// They are preserved by system calls and not used by the runtime or by stack accesses.
+pub const VARIABLE_REGISTERS: [Register; 3] = [R12, R13, Rbp];
+
+/// The registers that can hold temporaries, in order of priority
+///
+/// Temporaries never live across a call, so these can be clobbered by the runtime. `rax` and `rdx`
+/// are left for divisions and `rbx` for stack accesses.
+pub const TEMP_REGISTERS: [Register; 7] = [R8, R9, R10, R11, Rsi, Rdi, Rcx];
+
+impl Generate for Ir {
+    fn generate(&self, program: Program, ctx: &mut GenContext) -> Program {
+        // R15 is used as the stack base pointer
+        // allocate stack space
+        let mut program = program
+            .add(Mov(R15, Rsp.into()))
+            .add(Sub(Rsp, (ctx.symbol_table.size() as i32).into()));
+
+        // variables start at 0, like on the stack
+        ctx.registers = self
+            .registers
+            .iter()
+            .cloned()
+            .zip(VARIABLE_REGISTERS)
+            .collect();
+        for register in &VARIABLE_REGISTERS[..ctx.registers.len()] {
+            program = program.add(Xor(*register, (*register).into()));
+        }
+
+        let mut sources = HashSet::new();
+        for (id, block) in self.blocks.iter().enumerate() {
+            program = program.label(&label(id));
+
+            for inst in &block.insts {
+                program = match inst {
+                    Inst::Call(Runtime::PrintString(index)) => {
+                        let string = &self.strings[*index];
+                        let literal = format!("literal{index}");
+
+                        program
+                            .add(Mov(Rsi, Memory::from(literal.as_str()).into()))
+                            .add(Mov(Rdx, (string.len() as i32).into()))
+                            .add(Call("print".into()))
+                            .insert_data(&literal, string)
+                    }
+                    // lines added by optimizations share the location of another one
+                    Inst::Source(span) => {
+                        let name = source_label(*span);
+                        if sources.insert(name.clone()) {
+                            program.label(&name)
+                        } else {
+                            program
+                        }
+                    }
+                    inst => inst.generate(program, ctx),
+                };
+            }
+
+            program = terminator(&block.terminator, id + 1, program, ctx);
+        }
+
+        runtime(program)
+    }
+}
+
+impl Generate for Inst {
+    fn generate(&self, program: Program, ctx: &mut GenContext) -> Program {
+        match self {
+            Inst::Binary { dst, op, lhs, rhs } => {
+                // the result goes where lhs is, or in a free register, or on the stack
+                let result = match lhs {
+                    Value::Temp(temp) => ctx.temps.register(*temp),
+                    _ => None,
+                }
+                .or_else(|| ctx.temps.free())
+                .unwrap_or(Rax);
+
+                let (program, rhs_operand) = operands(lhs, rhs, result, program, ctx);
+
+                let program = match op {
+                    ArOp::Add => program.add(Add(result, rhs_operand)),
+                    ArOp::Sub => program.add(Sub(result, rhs_operand)),
+                    ArOp::Mul => program.add(IMul(result, rhs_operand)),
+                    ArOp::Div => {
+                        // idiv only takes a register
+                        let (program, divisor) = match rhs_operand {
+                            Operand::Reg(register) => (program, register),
+                            operand => (program.add(Mov(Rbx, operand)), Rbx),
+                        };
+                        let program = if result == Rax {
+                            program
+                        } else {
+                            program.add(Mov(Rax, result.into()))
+                        };
+                        // idiv divides rdx:rax, rdx holds the sign of rax for negative numbers
+                        let program = program
+                            .add(Mov(Rdx, Rax.into()))
+                            .add(Sar(Rdx, Operand::Imm(Imm8(63))))
+                            .add(IDiv(divisor));
+
+                        if result == Rax {
+                            program
+                        } else {
+                            program.add(Mov(result, Rax.into()))
+                        }
+                    }
+                    ArOp::Shl => program.add(Shl(result, shift(rhs))),
+                    ArOp::Shr => {
+                        // sar rounds toward negative infinity, so 2^n - 1 is added to negative
+                        // numbers first to round toward zero like idiv
+                        let Value::Const(n) = rhs else {
+                            unreachable!("Shifts are by a constant")
+                        };
+                        program
+                            .add(Mov(Rbx, result.into()))
+                            .add(Sar(Rbx, Operand::Imm(Imm8(63))))
+                            .add(Shr(Rbx, Operand::Imm(Imm8((64 - n) as i8))))
+                            .add(Add(result, Rbx.into()))
+                            .add(Sar(result, shift(rhs)))
+                    }
+                };
+
+                if result == Rax {
+                    ctx.temps.push(*dst);
+                    program.add(Push(Rax.into()))
+                } else {
+                    ctx.temps.assign(*dst, result);
+                    program
+                }
+            }
+            Inst::Store { var, value } => match (value, ctx.registers.get(var)) {
+                (_, Some(register)) => load(value, *register, program, ctx),
+                (Value::Const(n), None) => ctx.write(var, (*n).into(), program),
+                (Value::Temp(temp), None) => match ctx.temps.release(*temp) {
+                    Some(register) => ctx.write(var, register.into(), program),
+                    None => ctx.write(var, Rbx.into(), program.add(Pop(Rbx))),
+                },
+                (Value::Var(_), None) => {
+                    let program = load(value, Rbx, program, ctx);
+                    ctx.write(var, Rbx.into(), program)
+                }
+            },
+            Inst::Call(Runtime::PrintNumber(value)) => {
+                load(value, Rsi, program, ctx).add(Call("printn".into()))
+            }
+            Inst::Call(Runtime::Input(var)) => {
+                let end_addr = ctx.symbol_table.get(var).unwrap().end_addr() as i32;
+
+                // the input is read over the previous value, which must be on the stack
+                let program = match ctx.registers.get(var) {
+                    Some(register) => ctx.symbol_table.write(var, (*register).into(), program),
+                    None => program,
+                };
+
+                let program = program
+                    .add(Mov(Rsi, R15.into()))
+                    .add(Sub(Rsi, end_addr.into()))
+                    .add(Mov(Rdx, 8.into()))
+                    .add(Call("read".into()));
+
+                let program = ctx
+                    .symbol_table
+                    .access(var, program)
+                    .add(Sub(Rbx, 48.into()));
+
+                ctx.write(var, Rbx.into(), program)
+            }
+            Inst::Call(Runtime::PrintString(_)) | Inst::Source(_) => {
+                unreachable!("Strings and labels are generated with the program")
+            }
+        }
+    }
+}
+
+/// Puts a value in the given register
+fn load(value: &Value, register: Register, program: Program, ctx: &mut GenContext) -> Program {
+    match value {
+        Value::Const(n) => program.add(Mov(register, (*n).into())),
+        Value::Var(name) => ctx.read(name, register, program),
+        Value::Temp(temp) => match ctx.temps.release(*temp) {
+            Some(from) if from == register => program,
+            Some(from) => program.add(Mov(register, from.into())),
+            None => program.add(Pop(register)),
+        },
+    }
+}
+
+/// The count of a shift, which must be an 8 bits immediate
+fn shift(count: &Value) -> Operand {
+    match count {
+        Value::Const(n) => Operand::Imm(Imm8(*n as i8)),
+        _ => unreachable!("Shifts are by a constant"),
+    }
+}
+
+/// Puts `lhs` in the `result` register and returns `rhs` as an operand
+///
+/// The temporaries among them are released.
+fn operands(
+    lhs: &Value,
+    rhs: &Value,
+    result: Register,
+    program: Program,
+    ctx: &mut GenContext,
+) -> (Program, Operand) {
+    // temporaries on the stack are popped from the top, rhs is computed first when its
+    // subexpression needs more registers
+    let rhs_first = match (lhs, rhs) {
+        (Value::Temp(lhs), Value::Temp(rhs)) => {
+            ctx.temps.is_on_stack(*lhs) && ctx.temps.is_on_top(*rhs)
+        }
+        _ => false,
+    };
+
+    if rhs_first {
+        let (program, rhs) = operand(rhs, program, ctx);
+        (load(lhs, result, program, ctx), rhs)
+    } else {
+        // stack accesses go through rbx, so lhs is read before rhs is put in it
+        let program = load(lhs, result, program, ctx);
+        operand(rhs, program, ctx)
+    }
+}
+
+/// Returns a value as an operand, which can be an immediate or a register
+///
+/// Variables and temporaries on the stack are put in rbx.
+fn operand(value: &Value, program: Program, ctx: &mut GenContext) -> (Program, Operand) {
+    match value {
+        Value::Const(n) => (program, (*n).into()),
+        Value::Var(name) => match ctx.registers.get(name) {
+            Some(register) => (program, (*register).into()),
+            None => (ctx.read(name, Rbx, program), Rbx.into()),
+        },
+        Value::Temp(temp) => match ctx.temps.release(*temp) {
+            Some(register) => (program, register.into()),
+            None => (program.add(Pop(Rbx)), Rbx.into()),
+        },
+    }
+}
+
+/// Generates the end of a block, `next` being the block placed after it
+fn terminator(
+    terminator: &Terminator,
+    next: BlockId,
+    program: Program,
+    ctx: &mut GenContext,
+) -> Program {
+    match terminator {
+        Terminator::Jump(target) if *target == next => program,
+        Terminator::Jump(target) => program.add(Jmp(label(*target).into())),
+        Terminator::Branch {
+            relop,
+            lhs,
+            rhs,
+            then,
+            els,
+        } => {
+            // variables in registers can be compared directly
+            let program = match lhs.as_var().and_then(|var| ctx.registers.get(var)) {
+                Some(register) => {
+                    let register = *register;
+                    let (program, rhs) = operand(rhs, program, ctx);
+                    program.add(Cmp(register, rhs))
+                }
+                None => {
+                    let register = match lhs {
+                        Value::Temp(temp) => ctx.temps.register(*temp),
+                        _ => None,
+                    }
+                    .unwrap_or(Rax);
+                    let (program, rhs) = operands(lhs, rhs, register, program, ctx);
+                    program.add(Cmp(register, rhs))
+                }
+            };
+
+            let then: Memory = label(*then).into();
+            let program = program.add(match relop {
+                RelOp::Eq => Je(then),
+                RelOp::Ne => Jne(then),
+                RelOp::Ge => Jge(then),
+                RelOp::Gt => Jg(then),
+                RelOp::Le => Jle(then),
+                RelOp::Lt => Jl(then),
+            });
+
+            if *els == next {
+                program
+            } else {
+                program.add(Jmp(label(*els).into()))
+            }
+        }
+        Terminator::Exit => program.add(Jmp("exit".into())),
+    }
+}
+
+fn label(block: BlockId) -> String {
+    format!("bb{block}")
+}
+
+/// The label marking the start of a source line, e.g. `line3_1` for the line 3, column 1
+///
+/// Nothing jumps to it, it only points the assembly back at the source.
+fn source_label(span: Span) -> String {
+    format!("line{}_{}", span.line, span.col)
+}
+
+/// Checks if a label only marks the start of a source line
+pub fn is_source_label(name: &str) -> bool {
+    name.starts_with("line")
+}
+
+/// Adds the functions called by the program, and its exit point
+fn runtime(program: Program) -> Program {
+    let program = program
+        .func("printn")
+        // init, rax is the absolute value of the number
+        .add(Mov(Rax, Rsi.into()))
+        .add(Cmp(Rax, 0.into()))
+        .add(Jge("printn_positive".into()))
+        .add(Mov(Rbx, Rax.into()))
+        .add(Xor(Rax, Rax.into()))
+        .add(Sub(Rax, Rbx.into()))
+        .label("printn_positive")
+        .add(Xor(Rcx, Rcx.into()))
+        .add(Mov(Rbx, 10.into()))
+        // loop over digits and store them on the stack
+        .label("printn_inner")
+        .add(Xor(Rdx, Rdx.into()))
+        .add(IDiv(Rbx))
+        .add(Add(Rdx, ('0' as i32).into()));
+    let program = push_byte(program)
+        .add(Cmp(Rax, 0.into()))
+        .add(Jne("printn_inner".into()))
+        // the sign goes before the digits
+        .add(Cmp(Rsi, 0.into()))
+        .add(Jge("printn_print".into()))
+        .add(Mov(Rdx, ('-' as i32).into()));
+    let program = push_byte(program)
+        .label("printn_print")
+        // setup print params
+        .add(Mov(Rsi, Rsp.into()))
+        .add(Mov(Rdx, Rcx.into()))
+        .add(Call("print".into()))
+        // print keeps rdx, the length of the number
+        .add(Add(Rsp, Rdx.into()))
+        .func_end();
+
+    let program = program
+        .func("print")
+        .add(Mov(Rax, 1.into()))
+        .add(Mov(Rdi, 1.into()))
+        .add(Syscall)
+        .func_end();
+
+    let program = program
+        .func("read")
+        .add(Mov(Rax, 0.into()))
+        .add(Mov(Rdi, 0.into()))
+        .add(Syscall)
+        .func_end();
+
+    program
+        .label("exit")
+        .add(Mov(Rax, 60.into()))
+        .add(Mov(Rdi, 0.into()))
+        .add(Syscall)
+}
+
+/// Writes the low byte of rdx just below the stack top for `printn`, counting it in rcx
+///
+/// The digits are written from the last one. Pushing the byte shifted to the high end then moving
+/// rsp back up by 7 leaves exactly this byte, the zeros fall below rsp and are overwritten next.
+fn push_byte(program: Program) -> Program {
+    program
+        .add(Shl(Rdx, Operand::Imm(Imm8(56))))
+        .add(Push(Rdx.into()))
+        .add(Add(Rsp, 7.into()))
+        .add(Inc(Rcx))
+}
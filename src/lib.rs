@@ -0,0 +1,122 @@
+//! A Tiny BASIC compiler
+//!
+//! The compilation is split in steps which all work in memory:
+//!
+//! [`parse`] → [`check`] → [`optimize`] → [`assemble`] → [`link`]
+//!
+//! [`assemble`] goes through the [intermediate representation](ir) of the program, [`lower`] gives
+//! access to it.
+//!
+//! [`compile`] runs all of them at once, with every optimization.
+//!
+//! ```no_run
+//! let elf = tbc::compile("PRINT 1 + 1").unwrap();
+//! std::fs::write("a.out", elf).unwrap();
+//! ```
+
+use pest::Parser;
+
+pub mod cfg;
+pub mod check;
+pub mod error;
+pub mod generate;
+pub mod graphviz;
+pub mod interpreter;
+pub mod ir;
+pub mod optimize;
+pub mod parser;
+pub mod span;
+pub mod symbol_table;
+pub mod syntax_tree;
+
+pub use error::CompileError;
+pub use generate::{assemble, link};
+
+use error::Diagnostic;
+use graphviz::{Node, ToNodes};
+use ir::Ir;
+use optimize::Passes;
+use parser::{Rule, TbParser};
+use symbol_table::SymbolTable;
+use syntax_tree::{ParseContext, SyntaxTree};
+
+/// A successfully parsed program
+#[derive(Debug)]
+pub struct Parsed {
+    pub ast: SyntaxTree,
+    /// Every variable of the program
+    pub symbol_table: SymbolTable,
+    /// The parse tree, as a graphviz node
+    pub parse_tree: Node,
+}
+
+/// Parses a program and converts it to a syntax tree
+///
+/// Lines with errors are skipped so that every syntax error is reported at once.
+pub fn parse(source: &str) -> Result<Parsed, CompileError> {
+    let (source, errors) = parser::recover(source);
+    let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::from).collect();
+
+    let Ok(pairs) = TbParser::parse(Rule::file, &source) else {
+        return Err(CompileError::Syntax(diagnostics));
+    };
+    let parse_tree = pairs.to_nodes().first().unwrap().clone();
+
+    let mut ctx = ParseContext::default();
+    let ast = SyntaxTree::from_pairs(pairs, &mut ctx);
+    if let Err(errors) = &ast {
+        diagnostics.extend(errors.iter().map(Diagnostic::from));
+    }
+
+    if diagnostics.is_empty() {
+        Ok(Parsed {
+            ast: ast.unwrap(),
+            symbol_table: ctx.symbol_table,
+            parse_tree,
+        })
+    } else {
+        diagnostics.sort_by_key(|d| d.span.start);
+        Err(CompileError::Syntax(diagnostics))
+    }
+}
+
+/// Runs the semantic analysis
+///
+/// Returns the warnings if there is no error.
+pub fn check(parsed: &Parsed) -> Result<Vec<Diagnostic>, CompileError> {
+    let diagnostics = check::check(&parsed.ast, &parsed.symbol_table);
+
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        Err(CompileError::Semantic(diagnostics))
+    } else {
+        Ok(diagnostics)
+    }
+}
+
+/// Optimizes a syntax tree with the passes on [`SyntaxTree`]
+pub fn optimize(mut ast: SyntaxTree, passes: &Passes) -> SyntaxTree {
+    passes.run_ast(&mut ast);
+    ast
+}
+
+/// Converts a syntax tree to the [intermediate representation](ir), and optimizes it with the
+/// passes on [`Ir`]
+///
+/// [`assemble`] does this itself, this is mostly useful to look at the result.
+pub fn lower(ast: &SyntaxTree, passes: &Passes) -> Ir {
+    let mut ir = ir::lower(ast);
+    passes.run_ir(&mut ir);
+    ir
+}
+
+/// Compiles a program to an optimized ELF executable
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    let parsed = parse(source)?;
+    check(&parsed)?;
+
+    let passes = Passes::default();
+    let ost = optimize(parsed.ast, &passes);
+    let program = assemble(&ost, &parsed.symbol_table, &passes);
+
+    Ok(link(&program))
+}
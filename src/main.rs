@@ -1,92 +1,138 @@
 use std::{
     error::Error,
-    fs::{self, File},
-    io::prelude::Write,
-    path::PathBuf,
+    fs,
+    io::{self, BufWriter},
+    path::Path,
     process::Command,
 };
 
-use cli::Cli;
-use generate::{generate, OPTIMIZED, PARSE_TREE_DOT_FILE, UNOPTIMIZED};
-use graphviz::{compile_dot, Digraph, ToNodes};
-use optimize::Optimize;
-use parser::renamed_rules;
-use pest::Parser;
-use symbol_table::SymbolTable;
-use syntax_tree::SyntaxTree;
-
-use crate::parser::TbParser;
+use cli::{Artifact, ErrorFormat};
+use output::{
+    opt_report, Output, ASM_FILE, AST_DOT_FILE, CFG_DOT_FILE, OST_DOT_FILE, PARSE_TREE_DOT_FILE,
+};
+use repl::Repl;
+use tbc::{
+    assemble,
+    cfg::Cfg,
+    check,
+    error::{Diagnostic, RuntimeError},
+    graphviz::{Digraph, ToNode},
+    interpreter::Interpreter,
+    lower, optimize,
+    optimize::Passes,
+    parse, CompileError,
+};
 
 mod cli;
-mod error;
-mod generate;
-mod graphviz;
-mod optimize;
-mod parser;
-mod symbol_table;
-mod syntax_tree;
+mod output;
+mod repl;
+mod test;
+mod verify;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let cli: Cli = argh::from_env();
+    let mut cli = match cli::Command::from_env() {
+        cli::Command::Compile(cli) => cli,
+        cli::Command::Test(cli) => {
+            return match test::run(&cli)? {
+                0 => Ok(()),
+                failed => Err(format!("{failed} test(s) failed").into()),
+            };
+        }
+    };
+    let passes = cli.passes();
 
-    let mut symbol_table = SymbolTable::default();
+    let Some(file) = &cli.file else {
+        return Ok(Repl::new(passes).run()?);
+    };
 
-    let ast = parse(&cli.file, &mut symbol_table)?;
+    let output = Output::new(&cli)?;
 
-    generate(&ast, &mut symbol_table, &UNOPTIMIZED, false)?;
+    let path = file.to_string_lossy();
+    let content = fs::read_to_string(file)?;
 
-    // optimize AST
-    let ost = ast.optimize();
-    generate(&ost, &mut symbol_table, &OPTIMIZED, true)?;
+    // report every diagnostic, only keeping the summary as the error
+    let report_all = |error: CompileError| {
+        for diagnostic in error.diagnostics() {
+            report(diagnostic, cli.error_format, &path, &content);
+        }
+        error.to_string()
+    };
 
-    if cli.run {
-        match Command::new(format!("./{}", OPTIMIZED.bin)).status() {
-            Ok(status) => println!("{status}"),
-            Err(e) => eprintln!("{e}"),
-        };
+    let parsed = parse(&content).map_err(report_all)?;
+
+    if let Err(e) = output.graph(Artifact::ParseTree, PARSE_TREE_DOT_FILE, || {
+        parsed.parse_tree.clone()
+    }) {
+        eprintln!("{e}");
     }
 
-    Ok(())
-}
+    for diagnostic in check(&parsed).map_err(report_all)? {
+        report(&diagnostic, cli.error_format, &path, &content);
+    }
 
-fn parse(file: &PathBuf, symbol_table: &mut SymbolTable) -> Result<SyntaxTree, Box<dyn Error>> {
-    let content = fs::read_to_string(file)?;
+    if cli.verify_opt {
+        if !verify::verify(parsed, &passes)? {
+            return Err("optimized and unoptimized programs differ".into());
+        }
+        return Ok(());
+    }
 
-    // parse file
-    let parsed = TbParser::parse(parser::Rule::file, &content);
+    output.graph(Artifact::Ast, AST_DOT_FILE, || parsed.ast.to_node())?;
+    let unoptimized = (output.emits(Artifact::Unoptimized) || cli.opt_report)
+        .then(|| assemble(&parsed.ast, &parsed.symbol_table, &Passes::none()));
+    if let Some(unoptimized) = &unoptimized {
+        output.unoptimized(unoptimized)?;
+    }
 
-    let Ok(parsed) = parsed else {
-        let err = parsed.unwrap_err();
-        eprintln!(
-            "{}",
-            err.renamed_rules(renamed_rules)
-                .with_path(file.to_str().unwrap())
-        );
+    let ost = optimize(parsed.ast, &passes);
+    output.graph(Artifact::Ost, OST_DOT_FILE, || ost.to_node())?;
+    output.graph(Artifact::Cfg, CFG_DOT_FILE, || {
+        Digraph::from(&Cfg::new(&ost))
+    })?;
+    output.ir(|| lower(&ost, &passes))?;
+
+    if cli.interpret {
+        // the passes on the assembly do not run
+        if cli.opt_report {
+            print!("{}", passes.report());
+        }
+
+        let mut interpreter = Interpreter::new(io::stdin().lock(), BufWriter::new(io::stdout()));
+
+        return match interpreter.run(&ost) {
+            Ok(()) => Ok(()),
+            Err(RuntimeError::Io(e)) => Err(e.into()),
+            Err(e) => {
+                report(&Diagnostic::from(&e), cli.error_format, &path, &content);
+                Err("runtime error".into())
+            }
+        };
+    }
 
-        return Err("syntax error".into());
-    };
+    let program = assemble(&ost, &parsed.symbol_table, &passes);
+    output.asm(Artifact::Asm, ASM_FILE, &program)?;
+    output.elf(Artifact::Elf, output.bin(), &program)?;
 
-    // Write parse tree
-    {
-        let mut file = File::create(PARSE_TREE_DOT_FILE)?;
-        file.write_all(
-            Digraph::new(parsed.to_nodes().first().unwrap().clone())
-                .to_string()
-                .as_bytes(),
-        )?;
+    if let (true, Some(unoptimized)) = (cli.opt_report, &unoptimized) {
+        print!("{}", opt_report(&passes.report(), unoptimized, &program));
     }
-    if let Err(e) = compile_dot(PARSE_TREE_DOT_FILE) {
-        eprintln!("{e}");
-        eprintln!("Graphviz might not be installed. See https://graphviz.org/download/");
-    };
 
-    // convert to syntax tree
-    let ast = SyntaxTree::from_pairs(parsed, symbol_table);
-    let Ok(ast) = ast else {
-        let err = ast.unwrap_err();
-        eprintln!("{err}");
-        return Err(Box::new(err));
-    };
+    if cli.run {
+        // a bare file name would be searched in PATH
+        let bin = Path::new(".").join(output.bin());
+        match Command::new(bin).status() {
+            Ok(status) => println!("{status}"),
+            Err(e) => eprintln!("{e}"),
+        };
+    }
 
-    Ok(ast)
+    Ok(())
+}
+
+/// Shows a diagnostic to the user in the requested format
+fn report(diagnostic: &Diagnostic, format: ErrorFormat, path: &str, source: &str) {
+    match format {
+        ErrorFormat::Human => eprint!("{}", diagnostic.render(path, source)),
+        ErrorFormat::Json => println!("{}", diagnostic.to_json(path)),
+    }
 }
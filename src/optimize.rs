@@ -1,40 +1,250 @@
-use tiny_elf::asm::{Mnemonic, Program};
-
-pub trait Optimize {
-    fn optimize(self) -> Self;
-}
-
-/// Optimizes the assembly instructions of a [`Program`]
-impl Optimize for Program {
-    fn optimize(mut self) -> Self {
-        use tiny_elf::asm::{Immediate::*, Mnemonic::*, Operand::*};
-        let mut instructions = Vec::new();
-
-        let mut last: Option<Mnemonic> = None;
-
-        for inst in self.instructions {
-            let new_inst = match (&last, inst) {
-                // inc/dev is faster then add/sub 1
-                (_, Add(r, Imm(Imm8(1) | Imm16(1) | Imm32(1)))) => Inc(r),
-                (_, Sub(r, Imm(Imm8(1) | Imm16(1) | Imm32(1)))) => Dec(r),
-                // add/sub 0 is useless
-                (_, Add(_, Imm(Imm8(0) | Imm16(0) | Imm32(0)))) => continue,
-                (_, Sub(_, Imm(Imm8(0) | Imm16(0) | Imm32(0)))) => continue,
-                // xor is faster than mov 0
-                (_, Mov(r, Imm(Imm8(0) | Imm16(0) | Imm32(0)))) => Xor(r, r.into()),
-                // push then pop essentially means mov
-                (Some(Push(o)), Pop(r)) => {
-                    instructions.pop();
-                    Mov(r, o.clone())
-                }
-                (_, i) => i,
-            };
-
-            last = Some(new_inst.clone());
-            instructions.push(new_inst)
+use std::{
+    cell::{Ref, RefCell},
+    str::FromStr,
+};
+
+use tiny_elf::asm::Program;
+
+use crate::{ir::Ir, syntax_tree::SyntaxTree};
+
+mod branch_fold;
+mod const_fold;
+mod const_prop;
+mod dead_store;
+mod induction;
+mod licm;
+mod loops;
+mod peephole;
+mod regalloc;
+mod remove_noops;
+mod report;
+mod simplify;
+mod strength_reduce;
+mod unreachable;
+
+pub use branch_fold::*;
+pub use const_fold::*;
+pub use const_prop::*;
+pub use dead_store::*;
+pub use induction::*;
+pub use licm::*;
+pub use peephole::*;
+pub use regalloc::*;
+pub use remove_noops::*;
+pub use report::*;
+pub use simplify::*;
+pub use strength_reduce::*;
+pub use unreachable::*;
+
+/// Passes are run again until none of them changes anything, but at most this many times
+const MAX_ITERATIONS: usize = 16;
+
+/// A named transformation of a syntax tree, an [`Ir`] or a [`Program`]
+pub trait Pass<T> {
+    /// Identifies the pass on the command line, e.g. `const-fold`
+    fn name(&self) -> &'static str;
+
+    /// Transforms the target, returning whether anything changed
+    ///
+    /// Every change is recorded in the report.
+    fn run(&self, target: &mut T, report: &mut Report) -> bool;
+}
+
+/// Runs passes in order until a fixpoint is reached
+pub struct PassManager<T> {
+    passes: Vec<Box<dyn Pass<T>>>,
+}
+
+impl<T> PassManager<T> {
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    /// Adds a pass after the others
+    pub fn push(&mut self, pass: Box<dyn Pass<T>>) {
+        self.passes.push(pass);
+    }
+
+    /// The names of the passes, in order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs every pass until none of them changes anything
+    pub fn run(&self, target: &mut T, report: &mut Report) {
+        for _ in 0..MAX_ITERATIONS {
+            let mut changed = false;
+
+            for pass in &self.passes {
+                report.start(pass.name());
+                changed |= pass.run(target, report);
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+impl<T> Default for PassManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every pass on the syntax tree, in their default order
+fn ast_passes() -> Vec<Box<dyn Pass<SyntaxTree>>> {
+    vec![
+        Box::new(ConstProp),
+        Box::new(ConstFold),
+        Box::new(Simplify),
+        Box::new(BranchFold),
+        Box::new(Unreachable),
+        Box::new(DeadStore),
+        Box::new(RemoveNoOps),
+        Box::new(Licm),
+        Box::new(Induction),
+        Box::new(StrengthReduce),
+    ]
+}
+
+/// Every pass on the intermediate representation, in their default order
+fn ir_passes() -> Vec<Box<dyn Pass<Ir>>> {
+    vec![Box::new(RegAlloc)]
+}
+
+/// Every pass on the assembly, in their default order
+fn program_passes() -> Vec<Box<dyn Pass<Program>>> {
+    vec![Box::new(Peephole)]
+}
+
+/// How much the program is optimized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// No optimization at all
+    O0,
+    /// Only the passes on the syntax tree
+    O1,
+    /// Every pass
+    O2,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            s => Err(format!(
+                "unknown optimization level `{s}`, expected `0`, `1` or `2`"
+            )),
+        }
+    }
+}
+
+/// The passes run on each representation of the program
+pub struct Passes {
+    pub ast: PassManager<SyntaxTree>,
+    pub ir: PassManager<Ir>,
+    pub program: PassManager<Program>,
+    /// What the passes changed so far
+    report: RefCell<Report>,
+}
+
+impl Passes {
+    /// No pass at all
+    pub fn none() -> Self {
+        Self::level(OptLevel::O0)
+    }
+
+    /// The passes of an optimization level
+    pub fn level(level: OptLevel) -> Self {
+        let mut passes = Self {
+            ast: PassManager::new(),
+            ir: PassManager::new(),
+            program: PassManager::new(),
+            report: RefCell::new(Report::default()),
+        };
+
+        if level >= OptLevel::O1 {
+            passes.ast.passes = ast_passes();
+        }
+        if level >= OptLevel::O2 {
+            passes.ir.passes = ir_passes();
+            passes.program.passes = program_passes();
+        }
+
+        passes
+    }
+
+    /// Records every change made by the passes from now on
+    pub fn enable_report(&mut self) {
+        self.report = RefCell::new(Report::enabled());
+    }
+
+    /// What the passes changed so far, if the report is enabled
+    pub fn report(&self) -> Ref<'_, Report> {
+        self.report.borrow()
+    }
+
+    /// Runs the passes on the syntax tree
+    pub fn run_ast(&self, ast: &mut SyntaxTree) {
+        self.ast.run(ast, &mut self.report.borrow_mut());
+    }
+
+    /// Runs the passes on the intermediate representation
+    pub fn run_ir(&self, ir: &mut Ir) {
+        self.ir.run(ir, &mut self.report.borrow_mut());
+    }
+
+    /// Runs the passes on the assembly
+    pub fn run_program(&self, program: &mut Program) {
+        self.program.run(program, &mut self.report.borrow_mut());
+    }
+
+    /// The names of every pass, in order
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = self.ast.names();
+        names.extend(self.ir.names());
+        names.extend(self.program.names());
+        names
+    }
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Self::level(OptLevel::O2)
+    }
+}
+
+/// Parses a comma separated list of pass names, e.g. `const-fold,peephole`
+///
+/// Passes on the syntax tree always run first, then the ones on the intermediate representation,
+/// then the ones on the assembly.
+impl FromStr for Passes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut passes = Self::none();
+
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if let Some(pass) = ast_passes().into_iter().find(|p| p.name() == name) {
+                passes.ast.push(pass);
+            } else if let Some(pass) = ir_passes().into_iter().find(|p| p.name() == name) {
+                passes.ir.push(pass);
+            } else if let Some(pass) = program_passes().into_iter().find(|p| p.name() == name) {
+                passes.program.push(pass);
+            } else {
+                return Err(format!(
+                    "unknown pass `{name}`, expected one of: {}",
+                    Self::default().names().join(", ")
+                ));
+            }
         }
 
-        self.instructions = instructions;
-        self
+        Ok(passes)
     }
 }
@@ -0,0 +1,45 @@
+use super::{Pass, Report};
+use crate::syntax_tree::{Stmt, StmtKind, SyntaxTree};
+
+/// Replaces conditions comparing two constants by the branch that is always taken
+pub struct BranchFold;
+
+impl Pass<SyntaxTree> for BranchFold {
+    fn name(&self) -> &'static str {
+        "branch-fold"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        ast.visit_stmts_mut(&mut |stmt| {
+            let StmtKind::If { cond, .. } = &stmt.kind else {
+                return false;
+            };
+            let Ok(taken) = cond.try_execute() else {
+                return false;
+            };
+
+            let span = stmt.span;
+            let StmtKind::If { then, els, .. } = std::mem::replace(&mut stmt.kind, StmtKind::NoOp)
+            else {
+                unreachable!()
+            };
+
+            report.add(
+                Some(span),
+                match (taken, &els) {
+                    (true, _) => "replaced an IF always true by its THEN".into(),
+                    (false, Some(_)) => "replaced an IF always false by its ELSE".into(),
+                    (false, None) => "removed an IF always false".into(),
+                },
+            );
+
+            *stmt = if taken {
+                *then
+            } else {
+                els.map_or(Stmt::noop(span), |s| *s)
+            };
+
+            true
+        })
+    }
+}
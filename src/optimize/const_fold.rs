@@ -0,0 +1,39 @@
+use super::{Pass, Report};
+use crate::syntax_tree::{ArexprKind, SyntaxTree};
+
+/// Computes constant arithmetic expressions at compile time
+///
+/// Operations that would overflow or divide by zero are kept as is.
+pub struct ConstFold;
+
+impl Pass<SyntaxTree> for ConstFold {
+    fn name(&self) -> &'static str {
+        "const-fold"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        ast.visit_stmts_mut(&mut |stmt| {
+            let Some(arexpr) = stmt.arexpr_mut() else {
+                return false;
+            };
+
+            arexpr.visit_mut(&mut |arexpr| {
+                let ArexprKind::BinExpr { lhs, op, rhs } = &arexpr.kind else {
+                    return false;
+                };
+
+                match (&lhs.kind, &rhs.kind) {
+                    (ArexprKind::Num(lhs), ArexprKind::Num(rhs)) => match op.execute(*lhs, *rhs) {
+                        Some(n) => {
+                            report.add(Some(arexpr.span), format!("folded `{arexpr}` into {n}"));
+                            arexpr.kind = ArexprKind::Num(n);
+                            true
+                        }
+                        None => false,
+                    },
+                    _ => false,
+                }
+            })
+        })
+    }
+}
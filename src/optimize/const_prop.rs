@@ -0,0 +1,116 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Pass, Report};
+use crate::syntax_tree::{ArexprKind, CondOperandKind, Stmt, StmtKind, SyntaxTree};
+
+/// Replaces variables known to hold a constant by their value
+///
+/// Values are tracked from one line to the next, and forgotten at the lines a `GOTO` can jump to
+/// since they can be reached from anywhere.
+pub struct ConstProp;
+
+/// The variables known to hold a constant
+type Constants = HashMap<String, i32>;
+
+impl Pass<SyntaxTree> for ConstProp {
+    fn name(&self) -> &'static str {
+        "const-prop"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        // a `GOTO` to a removed line goes on to the next one, which is where the values are
+        // forgotten
+        let targets: HashSet<usize> = ast
+            .targets()
+            .iter()
+            .filter_map(|number| {
+                ast.lines()
+                    .iter()
+                    .position(|line| line.number() >= *number && !line.is_empty())
+            })
+            .collect();
+        let mut constants = Constants::new();
+        let mut changed = false;
+
+        for (index, line) in ast.lines_mut().iter_mut().enumerate() {
+            if targets.contains(&index) {
+                constants.clear();
+            }
+
+            // the next line is only reached by jumping to it
+            if !propagate(line.stmt_mut(), &mut constants, &mut changed, report) {
+                constants.clear();
+            }
+        }
+
+        changed
+    }
+}
+
+/// Substitutes the constants in a statement and records the ones it assigns
+///
+/// Returns whether the execution can continue after it.
+fn propagate(
+    stmt: &mut Stmt,
+    constants: &mut Constants,
+    changed: &mut bool,
+    report: &mut Report,
+) -> bool {
+    if let Some(arexpr) = stmt.arexpr_mut() {
+        *changed |= arexpr.visit_mut(&mut |arexpr| match &arexpr.kind {
+            ArexprKind::Ident(name) => match constants.get(name) {
+                Some(n) => {
+                    report.add(Some(arexpr.span), format!("replaced `{name}` by {n}"));
+                    arexpr.kind = ArexprKind::Num(*n);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        });
+    }
+
+    match &mut stmt.kind {
+        StmtKind::End | StmtKind::Goto(_) => false,
+        StmtKind::If { cond, then, els } => {
+            for operand in cond.operands_mut() {
+                if let CondOperandKind::Ident(name) = &operand.kind {
+                    if let Some(n) = constants.get(name) {
+                        report.add(Some(operand.span), format!("replaced `{name}` by {n}"));
+                        operand.kind = CondOperandKind::Num(*n);
+                        *changed = true;
+                    }
+                }
+            }
+
+            let mut then_constants = constants.clone();
+            let then_continues = propagate(then, &mut then_constants, changed, report);
+            let els_continues = match els {
+                Some(els) => propagate(els, constants, changed, report),
+                None => true,
+            };
+
+            // only the constants of the branches continuing after the IF are still known
+            match (then_continues, els_continues) {
+                (true, true) => constants.retain(|name, n| then_constants.get(name) == Some(n)),
+                (true, false) => *constants = then_constants,
+                (false, true) => {}
+                (false, false) => return false,
+            }
+
+            true
+        }
+        StmtKind::Input(name) => {
+            constants.remove(name);
+            true
+        }
+        StmtKind::Let(name, arexpr) => {
+            match arexpr.evaluate() {
+                Some(n) => constants.insert(name.clone(), n),
+                None => constants.remove(name),
+            };
+            true
+        }
+        StmtKind::Print(_) | StmtKind::NoOp => true,
+    }
+}
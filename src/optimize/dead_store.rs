@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+
+use super::{Pass, Report};
+use crate::syntax_tree::{StmtKind, SyntaxTree};
+
+/// Removes the `LET`s to variables that are never read
+///
+/// Assignments that could divide by zero are kept, so that the program still fails the same way.
+pub struct DeadStore;
+
+impl Pass<SyntaxTree> for DeadStore {
+    fn name(&self) -> &'static str {
+        "dead-store"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        let reads: HashSet<String> = ast
+            .lines()
+            .iter()
+            .flat_map(|line| line.stmt().reads())
+            .map(String::from)
+            .collect();
+
+        ast.visit_stmts_mut(&mut |stmt| match &stmt.kind {
+            StmtKind::Let(name, arexpr)
+                if !reads.contains(name) && !arexpr.may_divide_by_zero() =>
+            {
+                report.add(
+                    Some(stmt.span),
+                    format!("removed a LET to `{name}`, never read"),
+                );
+                stmt.kind = StmtKind::NoOp;
+                true
+            }
+            _ => false,
+        })
+    }
+}
@@ -0,0 +1,494 @@
+use super::{
+    loops::{self, Entry},
+    Pass, Report,
+};
+use crate::{
+    cfg::{BlockId, Cfg, Dominators, Loop},
+    span::Span,
+    syntax_tree::{ArOp, Arexpr, ArexprKind, CondOperandKind, RelOp, Stmt, StmtKind, SyntaxTree},
+};
+
+/// Uses the number of iterations of loops, when it is known at compile time
+///
+/// A counter is a variable only written by a `LET I = I + n` or `LET I = I - n`, which runs once in
+/// each iteration. The number of iterations is known when the loop is only left by an `IF`
+/// comparing a counter to a constant, and the counters are set to constants right before the loop.
+/// Then:
+///
+/// - a loop doing nothing but counting is replaced by the values of the counters after it
+/// - a `LET J = I * n` on a counter `I` becomes `LET J = J + m`, `J` being set before the loop
+///
+/// Values that would not fit in 32 bits are left to the program, which fails the same way.
+pub struct Induction;
+
+impl Pass<SyntaxTree> for Induction {
+    fn name(&self) -> &'static str {
+        "induction"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        let cfg = Cfg::new(ast);
+        let dominators = cfg.dominators();
+        let all = cfg.loops();
+        let mut rewrites = Vec::new();
+        let mut insertions = Vec::new();
+        // the blocks of the loops already changed, nested loops are left to the next run
+        let mut done: Vec<BlockId> = Vec::new();
+
+        for lp in &all {
+            if lp.blocks.iter().any(|block| done.contains(block)) {
+                continue;
+            }
+            let Some(counted) = Counted::new(&cfg, &dominators, &all, lp) else {
+                continue;
+            };
+            let (_, header) = cfg.lines(lp.header).next().unwrap();
+            let header = header.stmt().span.line;
+
+            let changes = match counted.finals() {
+                Some(finals) if counted.only_counts() => {
+                    report.add(
+                        Some(counted.test.1),
+                        format!(
+                            "computed the {} iterations of the loop starting at line {header}",
+                            counted.iterations,
+                        ),
+                    );
+                    for block in &lp.blocks {
+                        rewrites.extend(cfg.lines(*block).map(|(index, _)| (index, None)));
+                    }
+                    for entry in &counted.entries {
+                        for (counter, value) in &finals {
+                            let value = num(*value, counter.span);
+                            insertions.push((*entry, assign(counter.name, value)));
+                        }
+                    }
+                    true
+                }
+                _ => {
+                    let reduced = counted.reduce();
+                    for derived in &reduced {
+                        report.add(
+                            Some(derived.span),
+                            format!(
+                                "reduced `{} = {}` to `{} = {} + {}`",
+                                derived.name,
+                                derived.before,
+                                derived.name,
+                                derived.name,
+                                derived.step
+                            ),
+                        );
+
+                        let name = ident(derived.name, derived.span);
+                        let step = num(derived.step, derived.span);
+                        let update = Arexpr::binexpr(name, ArOp::Add, step);
+                        rewrites.push((derived.line, Some(assign(derived.name, update))));
+                        for entry in &counted.entries {
+                            let start = num(derived.start, derived.span);
+                            insertions.push((*entry, assign(derived.name, start)));
+                        }
+                    }
+                    !reduced.is_empty()
+                }
+            };
+
+            if changes {
+                done.extend(&lp.blocks);
+            }
+        }
+
+        let changed = !rewrites.is_empty();
+
+        for (index, stmt) in rewrites {
+            let line = ast.lines_mut()[index].stmt_mut();
+            *line = stmt.unwrap_or_else(|| Stmt::noop(line.span));
+        }
+        loops::insert(ast, insertions);
+
+        changed
+    }
+}
+
+/// A variable changed by the same amount in each iteration of a loop
+struct Counter<'a> {
+    name: &'a str,
+    step: i32,
+    /// Its value when entering the loop
+    start: i32,
+    /// The line changing it, with its index in [`SyntaxTree::lines`] and its block
+    line: (BlockId, usize),
+    span: Span,
+}
+
+/// A variable assigned a counter multiplied by a constant, which can be computed by additions
+struct Derived<'a> {
+    name: &'a str,
+    /// The expression computing it in the loop
+    before: &'a Arexpr,
+    /// Its value before the loop, so that adding the step gives the right value in the first
+    /// iteration
+    start: i32,
+    step: i32,
+    line: usize,
+    span: Span,
+}
+
+/// A loop whose number of iterations is known
+struct Counted<'c, 'a> {
+    cfg: &'c Cfg<'a>,
+    dominators: &'c Dominators,
+    lp: &'c Loop,
+    entries: Vec<Entry>,
+    counters: Vec<Counter<'a>>,
+    /// The block leaving the loop, and the location of its `IF`
+    test: (BlockId, Span),
+    /// How many times the test stays in the loop before leaving it
+    iterations: i64,
+    /// The block running after the loop
+    after: BlockId,
+    /// The blocks of the loops nested in this one
+    nested: Vec<BlockId>,
+}
+
+impl<'c, 'a> Counted<'c, 'a> {
+    fn new(
+        cfg: &'c Cfg<'a>,
+        dominators: &'c Dominators,
+        all: &[Loop],
+        lp: &'c Loop,
+    ) -> Option<Self> {
+        let entries = loops::entries(cfg, lp)?;
+        let [exiting] = loops::exiting(cfg, lp)[..] else {
+            return None;
+        };
+
+        let nested = all
+            .iter()
+            .filter(|other| other.header != lp.header && lp.contains(other.header))
+            .flat_map(|other| other.blocks.iter().copied())
+            .collect();
+
+        let mut counted = Self {
+            cfg,
+            dominators,
+            lp,
+            entries,
+            counters: Vec::new(),
+            test: (exiting, Default::default()),
+            iterations: 0,
+            after: 0,
+            nested,
+        };
+        counted.counters = counted.find_counters();
+
+        let (_, line) = cfg.lines(exiting).last().unwrap();
+        let StmtKind::If { cond, then, els } = &line.stmt().kind else {
+            return None;
+        };
+        counted.test.1 = line.stmt().span;
+
+        let then = cfg.destination(exiting, then)?;
+        let els = match els {
+            Some(els) => cfg.destination(exiting, els)?,
+            None => exiting + 1,
+        };
+        if lp.contains(then) == lp.contains(els) {
+            return None;
+        }
+        counted.after = if lp.contains(then) { els } else { then };
+
+        // `bound relop counter` is the same as `counter swapped bound`
+        let (name, relop, bound) = match cond.operands().map(|operand| &operand.kind) {
+            [CondOperandKind::Ident(name), CondOperandKind::Num(bound)] => {
+                (name, cond.relop(), *bound)
+            }
+            [CondOperandKind::Num(bound), CondOperandKind::Ident(name)] => {
+                (name, swap(cond.relop()), *bound)
+            }
+            _ => return None,
+        };
+        let exit = if lp.contains(then) {
+            negate(relop)
+        } else {
+            relop
+        };
+
+        let counter = counted.counters.iter().find(|c| c.name == name)?;
+        let first = counter.start as i64 + counter.step as i64 * counted.before_test(counter)?;
+        counted.iterations = exit_iteration(first, counter.step as i64, exit, bound as i64)?;
+
+        Some(counted)
+    }
+
+    /// The counters of the loop with a known value when entering it
+    fn find_counters(&self) -> Vec<Counter<'a>> {
+        let writes = loops::writes(self.cfg, self.lp);
+        let mut counters = Vec::new();
+
+        for block in self.once_per_iteration() {
+            for (index, line) in self.cfg.lines(block) {
+                let StmtKind::Let(name, arexpr) = &line.stmt().kind else {
+                    continue;
+                };
+                let ArexprKind::BinExpr { lhs, op, rhs } = &arexpr.kind else {
+                    continue;
+                };
+
+                let step = match (&lhs.kind, op, &rhs.kind) {
+                    (ArexprKind::Ident(var), ArOp::Add, ArexprKind::Num(n)) if var == name => *n,
+                    (ArexprKind::Ident(var), ArOp::Sub, ArexprKind::Num(n)) if var == name => {
+                        match n.checked_neg() {
+                            Some(n) => n,
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                if step == 0 || writes[name.as_str()] != 1 {
+                    continue;
+                }
+
+                let mut starts = self
+                    .entries
+                    .iter()
+                    .map(|entry| loops::entry_value(self.cfg, *entry, name));
+                let Some(Some(start)) = starts.next() else {
+                    continue;
+                };
+                if starts.any(|other| other != Some(start)) {
+                    continue;
+                }
+
+                counters.push(Counter {
+                    name,
+                    step,
+                    start,
+                    line: (block, index),
+                    span: line.stmt().span,
+                });
+            }
+        }
+
+        counters
+    }
+
+    /// The blocks running exactly once in each iteration, outside of nested loops
+    fn once_per_iteration(&self) -> Vec<BlockId> {
+        self.lp
+            .blocks
+            .iter()
+            .copied()
+            .filter(|block| !self.nested.contains(block))
+            .filter(|block| loops::runs_each_time(self.dominators, self.lp, *block))
+            .collect()
+    }
+
+    /// Whether a line runs before another one in each iteration, [`None`] if it depends on the
+    /// path taken
+    fn before(
+        &self,
+        (block, index): (BlockId, usize),
+        (other, other_index): (BlockId, usize),
+    ) -> Option<bool> {
+        if block == other {
+            Some(index < other_index)
+        } else if self.dominators.dominates(block, other) {
+            Some(true)
+        } else if self.dominators.dominates(other, block) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// 1 if a counter changes before the test in each iteration, 0 if it changes after
+    fn before_test(&self, counter: &Counter) -> Option<i64> {
+        let (test, _) = self.test;
+        let (index, _) = self.cfg.lines(test).last().unwrap();
+        self.before(counter.line, (test, index)).map(i64::from)
+    }
+
+    /// The value of each counter after the loop, if they all fit in 32 bits
+    fn finals(&self) -> Option<Vec<(&Counter<'a>, i32)>> {
+        self.counters
+            .iter()
+            .map(|counter| {
+                let changes = self.iterations + self.before_test(counter)?;
+                let value = counter.start as i64 + counter.step as i64 * changes;
+                Some((counter, i32::try_from(value).ok()?))
+            })
+            .collect()
+    }
+
+    /// Checks if the loop only changes its counters, then leaves to the lines following it
+    ///
+    /// Once its lines are removed, the execution goes on to the next line when entering the loop,
+    /// just like when leaving it.
+    fn only_counts(&self) -> bool {
+        let (first, last) = (self.lp.blocks[0], *self.lp.blocks.last().unwrap());
+        let (test, _) = self.test;
+        let contiguous = self.lp.blocks.len() == last - first + 1;
+        let falls_in = self
+            .entries
+            .iter()
+            .all(|entry| matches!(entry, Entry::Before(_)) || self.lp.header == first);
+
+        let writes = loops::writes(self.cfg, self.lp);
+        let counts = writes
+            .keys()
+            .all(|name| self.counters.iter().any(|c| c.name == *name));
+
+        let only_goto = self.lp.blocks.iter().all(|block| {
+            self.cfg.lines(*block).all(|(index, line)| {
+                let stmt = line.stmt();
+                match &stmt.kind {
+                    StmtKind::Goto(_) | StmtKind::Let(..) => true,
+                    StmtKind::If { then, els, .. } => {
+                        *block == test
+                            && index == self.cfg.blocks[test].lines.last().copied().unwrap()
+                            && matches!(then.kind, StmtKind::Goto(_))
+                            && els
+                                .as_ref()
+                                .is_none_or(|els| matches!(els.kind, StmtKind::Goto(_)))
+                    }
+                    _ => false,
+                }
+            })
+        });
+
+        contiguous && falls_in && counts && only_goto && self.after == last + 1
+    }
+
+    /// The `LET J = I * n` on counters which can be computed by additions
+    fn reduce(&self) -> Vec<Derived<'a>> {
+        let writes = loops::writes(self.cfg, self.lp);
+        let mut reduced = Vec::new();
+
+        for block in self.once_per_iteration() {
+            for (index, line) in self.cfg.lines(block) {
+                let StmtKind::Let(name, arexpr) = &line.stmt().kind else {
+                    continue;
+                };
+                let ArexprKind::BinExpr { lhs, op, rhs } = &arexpr.kind else {
+                    continue;
+                };
+                let (ArexprKind::Ident(var), ArexprKind::Num(n)) = (&lhs.kind, &rhs.kind) else {
+                    continue;
+                };
+                let factor = match op {
+                    ArOp::Mul => *n,
+                    ArOp::Shl => match u32::try_from(*n).ok().and_then(|n| 2i32.checked_pow(n)) {
+                        Some(factor) => factor,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let Some(counter) = self.counters.iter().find(|c| c.name == var) else {
+                    continue;
+                };
+                if name == var
+                    || writes[name.as_str()] != 1
+                    || !loops::reads_after(self.cfg, self.dominators, self.lp, (block, index), name)
+                    || !loops::same_after(self.cfg, self.dominators, self.lp, block, name)
+                {
+                    continue;
+                }
+
+                // the counter may have changed already in the first iteration
+                let Some(before) = self.before(counter.line, (block, index)) else {
+                    continue;
+                };
+                let (start, step) = (counter.start as i64, counter.step as i64);
+                let first = start + step * (i64::from(before) - 1);
+                let last = start + step * (self.iterations + i64::from(before));
+
+                let start = i32::try_from(first * factor as i64).ok();
+                let step = i32::try_from(step * factor as i64).ok();
+                let last = i32::try_from(last * factor as i64).ok();
+                let (Some(start), Some(step), Some(_)) = (start, step, last) else {
+                    continue;
+                };
+
+                reduced.push(Derived {
+                    name,
+                    before: arexpr,
+                    start,
+                    step,
+                    line: index,
+                    span: line.stmt().span,
+                });
+            }
+        }
+
+        reduced
+    }
+}
+
+/// The first iteration in which a counter, equal to `first` in the test of the first iteration,
+/// satisfies `exit`
+fn exit_iteration(first: i64, step: i64, exit: RelOp, bound: i64) -> Option<i64> {
+    match exit {
+        RelOp::Eq => {
+            let distance = bound - first;
+            (distance % step == 0 && distance / step >= 0).then_some(distance / step)
+        }
+        RelOp::Ne => Some(i64::from(first == bound)),
+        RelOp::Ge | RelOp::Gt => {
+            let bound = if exit == RelOp::Gt { bound + 1 } else { bound };
+            if first >= bound {
+                Some(0)
+            } else if step > 0 {
+                Some((bound - first + step - 1) / step)
+            } else {
+                None
+            }
+        }
+        RelOp::Le | RelOp::Lt => {
+            let bound = if exit == RelOp::Lt { bound - 1 } else { bound };
+            if first <= bound {
+                Some(0)
+            } else if step < 0 {
+                Some((first - bound - step - 1) / -step)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The operator giving the opposite result
+fn negate(relop: RelOp) -> RelOp {
+    match relop {
+        RelOp::Eq => RelOp::Ne,
+        RelOp::Ne => RelOp::Eq,
+        RelOp::Ge => RelOp::Lt,
+        RelOp::Gt => RelOp::Le,
+        RelOp::Le => RelOp::Gt,
+        RelOp::Lt => RelOp::Ge,
+    }
+}
+
+/// The operator giving the same result with its operands swapped
+fn swap(relop: RelOp) -> RelOp {
+    match relop {
+        RelOp::Eq | RelOp::Ne => relop,
+        RelOp::Ge => RelOp::Le,
+        RelOp::Gt => RelOp::Lt,
+        RelOp::Le => RelOp::Ge,
+        RelOp::Lt => RelOp::Gt,
+    }
+}
+
+fn assign(name: &str, arexpr: Arexpr) -> Stmt {
+    let span = arexpr.span;
+    Stmt::new(StmtKind::Let(name.to_string(), arexpr), span)
+}
+
+fn num(n: i32, span: Span) -> Arexpr {
+    Arexpr::new(ArexprKind::Num(n), span)
+}
+
+fn ident(name: &str, span: Span) -> Arexpr {
+    Arexpr::new(ArexprKind::Ident(name.to_string()), span)
+}
@@ -0,0 +1,80 @@
+use super::{loops, Pass, Report};
+use crate::{
+    cfg::Cfg,
+    syntax_tree::{ArexprKind, Stmt, StmtKind, SyntaxTree},
+};
+
+/// Moves the `LET`s computing the same value in each iteration of a loop before the loop
+///
+/// The variables of the expression must not be written in the loop, and the variable assigned
+/// only by this `LET`, which must run in each iteration before every read of the variable in the
+/// loop. If the loop can be left before the `LET`, the variable must not be read after the loop.
+/// Expressions that could divide by zero are not moved, as they might never have been computed.
+///
+/// Loops entered from the start of the program or by a `GOTO` in an `IF` are left as is, there is
+/// no place to put the moved lines.
+pub struct Licm;
+
+impl Pass<SyntaxTree> for Licm {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        let cfg = Cfg::new(ast);
+        let dominators = cfg.dominators();
+        // indexes of the moved lines, a line in nested loops is moved out of the outer one
+        let mut moved = Vec::new();
+        let mut insertions = Vec::new();
+
+        for lp in cfg.loops() {
+            let Some(entries) = loops::entries(&cfg, &lp) else {
+                continue;
+            };
+            let writes = loops::writes(&cfg, &lp);
+            let (_, header) = cfg.lines(lp.header).next().unwrap();
+            let header = header.stmt().span.line;
+
+            for block in &lp.blocks {
+                if !loops::runs_each_time(&dominators, &lp, *block) {
+                    continue;
+                }
+
+                for (index, line) in cfg.lines(*block) {
+                    let stmt = line.stmt();
+                    let StmtKind::Let(name, arexpr) = &stmt.kind else {
+                        continue;
+                    };
+
+                    let invariant = matches!(arexpr.kind, ArexprKind::BinExpr { .. })
+                        && !arexpr.may_divide_by_zero()
+                        && arexpr.variables().iter().all(|v| !writes.contains_key(v))
+                        && writes[name.as_str()] == 1
+                        && loops::reads_after(&cfg, &dominators, &lp, (*block, index), name)
+                        && loops::same_after(&cfg, &dominators, &lp, *block, name);
+                    if !invariant || moved.contains(&index) {
+                        continue;
+                    }
+
+                    report.add(
+                        Some(stmt.span),
+                        format!("moved `{stmt}` out of the loop starting at line {header}"),
+                    );
+                    moved.push(index);
+                    for entry in &entries {
+                        let kind = StmtKind::Let(name.clone(), arexpr.clone());
+                        insertions.push((*entry, Stmt::new(kind, stmt.span)));
+                    }
+                }
+            }
+        }
+
+        for index in &moved {
+            let stmt = ast.lines_mut()[*index].stmt_mut();
+            *stmt = Stmt::noop(stmt.span);
+        }
+        loops::insert(ast, insertions);
+
+        !moved.is_empty()
+    }
+}
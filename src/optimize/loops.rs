@@ -0,0 +1,186 @@
+//! What the passes on loops have in common: finding where the execution enters a loop, and adding
+//! lines there.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    cfg::{Cfg, Dominators, Loop},
+    syntax_tree::{ArexprKind, Line, Stmt, StmtKind, SyntaxTree},
+};
+
+/// A place where lines running once before a loop can be added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    /// Before a `GOTO` to the header, taking its number so that jumps to it go through the new
+    /// lines
+    Before(usize),
+    /// After a line going on to the header, taking its number so that no jump goes to the new lines
+    After(usize),
+}
+
+impl Entry {
+    /// Where the new lines go in [`SyntaxTree::lines`]
+    fn index(self) -> usize {
+        match self {
+            Entry::Before(index) => index,
+            Entry::After(index) => index + 1,
+        }
+    }
+
+    /// The line the new lines are numbered after
+    fn line(self) -> usize {
+        match self {
+            Entry::Before(index) | Entry::After(index) => index,
+        }
+    }
+}
+
+/// The places where the execution enters a loop from outside of it
+///
+/// [`None`] is returned if the loop is entered from the start of the program or by a `GOTO` in an
+/// `IF`, where no line can be added for the loop only.
+pub fn entries(cfg: &Cfg, lp: &Loop) -> Option<Vec<Entry>> {
+    if lp.header == 0 {
+        return None;
+    }
+
+    let reached = cfg.reachable();
+    let mut entries = Vec::new();
+
+    for predecessor in &cfg.predecessors()[lp.header] {
+        if lp.contains(*predecessor) || !reached[*predecessor] {
+            continue;
+        }
+
+        let (index, line) = cfg.lines(*predecessor).last()?;
+        let stmt = line.stmt();
+
+        if matches!(stmt.kind, StmtKind::Goto(_)) {
+            entries.push(Entry::Before(index));
+        } else if stmt.targets().iter().all(|t| cfg.target(*t) != lp.header) {
+            entries.push(Entry::After(index));
+        } else {
+            return None;
+        }
+    }
+
+    Some(entries)
+}
+
+/// The value of a variable when entering a loop, if it is a constant assigned in the block
+/// before it
+pub fn entry_value(cfg: &Cfg, entry: Entry, name: &str) -> Option<i32> {
+    let (_, line) = cfg
+        .lines(cfg.block_of(entry.line()))
+        .filter(|(index, _)| *index < entry.index())
+        .filter(|(_, line)| line.stmt().writes().contains(&name))
+        .last()?;
+
+    match &line.stmt().kind {
+        StmtKind::Let(_, arexpr) => match arexpr.kind {
+            ArexprKind::Num(n) => Some(n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// How many times each variable is written in a loop
+pub fn writes<'a>(cfg: &Cfg<'a>, lp: &Loop) -> BTreeMap<&'a str, usize> {
+    let mut writes = BTreeMap::new();
+
+    for block in &lp.blocks {
+        for (_, line) in cfg.lines(*block) {
+            for name in line.stmt().writes() {
+                *writes.entry(name).or_default() += 1;
+            }
+        }
+    }
+
+    writes
+}
+
+/// Checks if a block runs in each iteration of a loop, before going back to its header
+pub fn runs_each_time(dominators: &Dominators, lp: &Loop, block: usize) -> bool {
+    lp.latches
+        .iter()
+        .all(|latch| dominators.dominates(block, *latch))
+}
+
+/// Checks if a variable assigned in `block` holds the same value after the loop when assigned
+/// before the loop instead
+///
+/// This is the case when `block` runs before the execution can leave the loop, or when the variable
+/// is not read outside of it, as with a loop entered by a `GOTO` to its test.
+pub fn same_after(cfg: &Cfg, dominators: &Dominators, lp: &Loop, block: usize, name: &str) -> bool {
+    let before_exit = exiting(cfg, lp)
+        .iter()
+        .all(|exiting| dominators.dominates(block, *exiting));
+    let read_outside = (0..cfg.blocks.len())
+        .filter(|other| !lp.contains(*other))
+        .flat_map(|other| cfg.lines(other))
+        .any(|(_, line)| line.stmt().reads().contains(&name));
+
+    before_exit || !read_outside
+}
+
+/// Checks if every read of a variable in a loop comes after the line at `index`, in `block`
+///
+/// The variable then holds the value assigned by that line, instead of a value from before the
+/// loop.
+pub fn reads_after(
+    cfg: &Cfg,
+    dominators: &Dominators,
+    lp: &Loop,
+    (block, index): (usize, usize),
+    name: &str,
+) -> bool {
+    lp.blocks.iter().all(|other| {
+        cfg.lines(*other)
+            .filter(|(_, line)| line.stmt().reads().contains(&name))
+            .all(|(read, _)| {
+                if *other == block {
+                    read > index
+                } else {
+                    *other != lp.header && dominators.dominates(block, *other)
+                }
+            })
+    })
+}
+
+/// The blocks of a loop from which the execution can leave it
+pub fn exiting(cfg: &Cfg, lp: &Loop) -> Vec<usize> {
+    lp.blocks
+        .iter()
+        .copied()
+        .filter(|block| {
+            cfg.blocks[*block]
+                .successors
+                .iter()
+                .any(|edge| !lp.contains(edge.to))
+        })
+        .collect()
+}
+
+/// Adds statements at the entries of loops, in order
+///
+/// Lines added at the same place keep the order they were given in.
+pub fn insert(ast: &mut SyntaxTree, insertions: Vec<(Entry, Stmt)>) {
+    // lines after a line go before the ones before the next line, which also run when going on
+    let mut sorted: BTreeMap<(usize, bool), Vec<(usize, Stmt)>> = BTreeMap::new();
+    for (entry, stmt) in insertions {
+        let number = ast.lines()[entry.line()].number();
+        sorted
+            .entry((entry.index(), matches!(entry, Entry::Before(_))))
+            .or_default()
+            .push((number, stmt));
+    }
+
+    // from the end, so that the indexes of the next insertions do not move
+    for ((index, _), stmts) in sorted.into_iter().rev() {
+        let lines = stmts
+            .into_iter()
+            .map(|(number, stmt)| Line::new(number, stmt));
+        ast.lines_mut().splice(index..index, lines);
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+
+use tiny_elf::asm::{
+    Immediate::*, Memory, Mnemonic, Mnemonic::*, Operand, Operand::*, Program, Register::*,
+};
+
+use super::{Pass, Report};
+use crate::ir::is_source_label;
+
+/// Replaces short sequences of instructions with faster equivalents
+///
+/// Every rule is tried at every instruction, the first one matching is applied, and the program is
+/// swept again until no rule matches anymore. Labels are instructions of their own, so a sequence
+/// never matches across one, except in the rules about jumps. The labels of source lines are set
+/// aside, they go before the rewrite of the instructions they preceded.
+///
+/// The number of times each rule applied is reported, with the labels preceding the rewrites.
+pub struct Peephole;
+
+impl Pass<Program> for Peephole {
+    fn name(&self) -> &'static str {
+        "peephole"
+    }
+
+    fn run(&self, program: &mut Program, report: &mut Report) -> bool {
+        // the label preceding each rewrite, by rule
+        let mut hits: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+
+        loop {
+            let (old, mut sources) = split_sources(std::mem::take(&mut program.instructions));
+            let labels = Labels::new(&old);
+            let mut instructions = Vec::with_capacity(old.len());
+            // the code before the first label is the start of the program
+            let mut label = String::from("start");
+            let mut swept = false;
+            let mut index = 0;
+
+            'insts: while index < old.len() {
+                if let Label(name) = &old[index] {
+                    label.clone_from(name);
+                }
+
+                for rule in RULES {
+                    let Some(window) = old.get(index..index + rule.window) else {
+                        continue;
+                    };
+
+                    if let Some(replacement) = (rule.apply)(window, &labels) {
+                        hits.entry(rule.name).or_default().push(label.clone());
+                        for source in &mut sources[index..index + rule.window] {
+                            instructions.append(source);
+                        }
+                        instructions.extend(replacement);
+                        index += rule.window;
+                        swept = true;
+                        continue 'insts;
+                    }
+                }
+
+                instructions.append(&mut sources[index]);
+                instructions.push(old[index].clone());
+                index += 1;
+            }
+            instructions.append(&mut sources[old.len()]);
+
+            program.instructions = instructions;
+
+            if !swept {
+                break;
+            }
+        }
+
+        for (rule, labels) in &hits {
+            let mut places: Vec<&str> = Vec::new();
+            for label in labels {
+                if !places.contains(&label.as_str()) {
+                    places.push(label);
+                }
+            }
+            report.add(
+                None,
+                format!(
+                    "{rule} applied {} time(s), in {}",
+                    labels.len(),
+                    places.join(", ")
+                ),
+            );
+        }
+
+        !hits.is_empty()
+    }
+}
+
+/// A rewrite of a few consecutive instructions
+struct Rule {
+    /// Identifies the rule in the hit counts
+    name: &'static str,
+    /// How many instructions the rule looks at
+    window: usize,
+    /// The replacement of the instructions, if the rule applies to them
+    apply: fn(&[Mnemonic], &Labels) -> Option<Vec<Mnemonic>>,
+}
+
+/// Every rule, longer ones first so that they are tried before the shorter ones they contain
+const RULES: &[Rule] = &[
+    Rule {
+        name: "store-reload",
+        window: 10,
+        apply: store_reload,
+    },
+    Rule {
+        name: "invert-branch",
+        window: 3,
+        apply: invert_branch,
+    },
+    Rule {
+        name: "push-pop",
+        window: 2,
+        apply: push_pop,
+    },
+    Rule {
+        name: "jump-to-next",
+        window: 2,
+        apply: jump_to_next,
+    },
+    Rule {
+        name: "dead-after-jump",
+        window: 2,
+        apply: dead_after_jump,
+    },
+    Rule {
+        name: "jump-to-jump",
+        window: 1,
+        apply: jump_to_jump,
+    },
+    Rule {
+        name: "mov-self",
+        window: 1,
+        apply: mov_self,
+    },
+    Rule {
+        name: "inc-dec",
+        window: 1,
+        apply: inc_dec,
+    },
+    Rule {
+        name: "add-sub-zero",
+        window: 1,
+        apply: add_sub_zero,
+    },
+    Rule {
+        name: "xor-zero",
+        window: 1,
+        apply: xor_zero,
+    },
+];
+
+/// Sets the labels of source lines aside, as nothing jumps to them
+///
+/// Returns the other instructions, and the source labels preceding each of them, the last ones
+/// being at the end of the program.
+fn split_sources(instructions: Vec<Mnemonic>) -> (Vec<Mnemonic>, Vec<Vec<Mnemonic>>) {
+    let mut others = Vec::with_capacity(instructions.len());
+    let mut sources = vec![Vec::new()];
+
+    for inst in instructions {
+        match &inst {
+            Label(name) if is_source_label(name) => sources.last_mut().unwrap().push(inst),
+            _ => {
+                others.push(inst);
+                sources.push(Vec::new());
+            }
+        }
+    }
+
+    (others, sources)
+}
+
+/// Where the jumps to each label end up
+struct Labels {
+    /// The labels directly followed by an unconditional jump, with the target of that jump
+    forwards: Vec<(Memory, Memory)>,
+}
+
+impl Labels {
+    fn new(instructions: &[Mnemonic]) -> Self {
+        let mut forwards = Vec::new();
+
+        for (index, inst) in instructions.iter().enumerate() {
+            let Label(name) = inst else {
+                continue;
+            };
+
+            // several labels can be at the same place
+            let next = instructions[index + 1..]
+                .iter()
+                .find(|inst| !matches!(inst, Label(_)));
+            if let Some(Jmp(target)) = next {
+                forwards.push((Memory::from(name.as_str()), target.clone()));
+            }
+        }
+
+        Self { forwards }
+    }
+
+    /// The last label of a chain of jumps starting at `label`, if it is not `label` itself
+    ///
+    /// Jumps going round in circles have no end, [`None`] is returned.
+    fn resolve(&self, label: &Memory) -> Option<Memory> {
+        let mut seen = vec![label];
+        let mut current = label;
+
+        while let Some((_, next)) = self.forwards.iter().find(|(from, _)| from == current) {
+            if seen.contains(&next) {
+                return None;
+            }
+            seen.push(next);
+            current = next;
+        }
+
+        (current != label).then(|| current.clone())
+    }
+}
+
+/// Checks if an instruction is the given label
+fn is_label(inst: &Mnemonic, label: &Memory) -> bool {
+    matches!(inst, Label(name) if Memory::from(name.as_str()) == *label)
+}
+
+/// A store to a variable on the stack directly followed by a load of the same variable reuses the
+/// stored value
+fn store_reload(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    let (store, Push(value)) = stack_access(&insts[..5])? else {
+        return None;
+    };
+    let (load, Pop(register)) = stack_access(&insts[5..])? else {
+        return None;
+    };
+    // the registers used to reach the stack change in between
+    if store != load || matches!(value, Reg(Rsp | R14 | R15)) {
+        return None;
+    }
+
+    let mut replacement = insts[..5].to_vec();
+    replacement.push(Mov(*register, value.clone()));
+    Some(replacement)
+}
+
+/// The address and the push or pop of an access to a variable on the stack, as generated by the
+/// [`SymbolTable`](crate::symbol_table::SymbolTable)
+fn stack_access(insts: &[Mnemonic]) -> Option<(&Operand, &Mnemonic)> {
+    match insts {
+        [Mov(R14, Reg(Rsp)), Mov(Rsp, Reg(R15)), Sub(Rsp, address), access, Mov(Rsp, Reg(R14))] => {
+            Some((address, access))
+        }
+        _ => None,
+    }
+}
+
+/// A conditional jump over an unconditional one becomes the opposite conditional jump
+fn invert_branch(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    let [branch, Jmp(target), label] = insts else {
+        return None;
+    };
+
+    let inverted = match branch {
+        Je(over) if is_label(label, over) => Jne(target.clone()),
+        Jne(over) if is_label(label, over) => Je(target.clone()),
+        Jg(over) if is_label(label, over) => Jle(target.clone()),
+        Jle(over) if is_label(label, over) => Jg(target.clone()),
+        Jge(over) if is_label(label, over) => Jl(target.clone()),
+        Jl(over) if is_label(label, over) => Jge(target.clone()),
+        _ => return None,
+    };
+
+    Some(vec![inverted, label.clone()])
+}
+
+/// A push directly followed by a pop is a move
+fn push_pop(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Push(operand), Pop(register)] => Some(vec![Mov(*register, operand.clone())]),
+        _ => None,
+    }
+}
+
+/// A jump to the label right after it does nothing
+fn jump_to_next(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Jmp(target), label] if is_label(label, target) => Some(vec![label.clone()]),
+        _ => None,
+    }
+}
+
+/// Nothing after an unconditional jump is executed, until the next label
+fn dead_after_jump(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [jump @ Jmp(_), inst] if !matches!(inst, Label(_)) => Some(vec![jump.clone()]),
+        _ => None,
+    }
+}
+
+/// A jump to an unconditional jump goes directly to the target of the latter
+fn jump_to_jump(insts: &[Mnemonic], labels: &Labels) -> Option<Vec<Mnemonic>> {
+    let inst = match &insts[0] {
+        Jmp(target) => Jmp(labels.resolve(target)?),
+        Je(target) => Je(labels.resolve(target)?),
+        Jne(target) => Jne(labels.resolve(target)?),
+        Jg(target) => Jg(labels.resolve(target)?),
+        Jge(target) => Jge(labels.resolve(target)?),
+        Jl(target) => Jl(labels.resolve(target)?),
+        Jle(target) => Jle(labels.resolve(target)?),
+        _ => return None,
+    };
+
+    Some(vec![inst])
+}
+
+/// Moving a register to itself does nothing
+fn mov_self(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Mov(register, Reg(from))] if register == from => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// inc/dec is faster than add/sub 1
+fn inc_dec(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Add(register, Imm(Imm8(1) | Imm16(1) | Imm32(1)))] => Some(vec![Inc(*register)]),
+        [Sub(register, Imm(Imm8(1) | Imm16(1) | Imm32(1)))] => Some(vec![Dec(*register)]),
+        _ => None,
+    }
+}
+
+/// add/sub 0 is useless
+fn add_sub_zero(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Add(_, Imm(Imm8(0) | Imm16(0) | Imm32(0)))]
+        | [Sub(_, Imm(Imm8(0) | Imm16(0) | Imm32(0)))] => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// xor is faster than mov 0
+fn xor_zero(insts: &[Mnemonic], _: &Labels) -> Option<Vec<Mnemonic>> {
+    match insts {
+        [Mov(register, Imm(Imm8(0) | Imm16(0) | Imm32(0)))] => {
+            Some(vec![Xor(*register, (*register).into())])
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use super::{Pass, Report};
+use crate::ir::{Inst, Ir, Value, VARIABLE_REGISTERS};
+
+/// Keeps the most used variables in registers instead of on the stack
+///
+/// Variables are ranked by how many times they are read or written in the program.
+pub struct RegAlloc;
+
+impl Pass<Ir> for RegAlloc {
+    fn name(&self) -> &'static str {
+        "regalloc"
+    }
+
+    fn run(&self, ir: &mut Ir, report: &mut Report) -> bool {
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+
+        for block in &ir.blocks {
+            let reads = block
+                .insts
+                .iter()
+                .flat_map(Inst::operands)
+                .chain(block.terminator.operands())
+                .filter_map(Value::as_var);
+            let writes = block.insts.iter().filter_map(Inst::written);
+
+            for var in reads.chain(writes) {
+                *uses.entry(var).or_default() += 1;
+            }
+        }
+
+        let mut ranked: Vec<(&str, usize)> = uses.into_iter().collect();
+        // ties are broken by name so that the allocation is deterministic
+        ranked.sort_by(|(a, a_uses), (b, b_uses)| b_uses.cmp(a_uses).then(a.cmp(b)));
+
+        let registers: Vec<String> = ranked
+            .into_iter()
+            .take(VARIABLE_REGISTERS.len())
+            .map(|(var, _)| var.to_string())
+            .collect();
+
+        let changed = registers != ir.registers;
+        if changed {
+            report.add(None, format!("kept {} in registers", registers.join(", ")));
+        }
+        ir.registers = registers;
+        changed
+    }
+}
@@ -0,0 +1,29 @@
+use super::{Pass, Report};
+use crate::syntax_tree::SyntaxTree;
+
+/// Removes the lines that do nothing
+pub struct RemoveNoOps;
+
+impl Pass<SyntaxTree> for RemoveNoOps {
+    fn name(&self) -> &'static str {
+        "remove-noops"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        let lines = ast.lines_mut();
+        let len = lines.len();
+
+        lines.retain(|line| {
+            // lines without a number are comments or blank in the source
+            if line.is_empty() && line.number() != 0 {
+                report.add(
+                    Some(line.stmt().span),
+                    "dropped the line, empty".to_string(),
+                );
+            }
+            !line.is_empty()
+        });
+
+        lines.len() != len
+    }
+}
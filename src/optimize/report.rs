@@ -0,0 +1,94 @@
+use std::fmt::Display;
+
+use crate::span::Span;
+
+/// A change made by an optimization pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The name of the pass
+    pub pass: &'static str,
+    /// What was changed in the source, if it comes from there
+    pub span: Option<Span>,
+    pub description: String,
+}
+
+/// Every change made by the optimization passes, for `--opt-report`
+///
+/// Nothing is recorded unless the report is [enabled](Report::enabled).
+#[derive(Debug, Default)]
+pub struct Report {
+    changes: Vec<Change>,
+    enabled: bool,
+    /// The name of the pass being run
+    pass: &'static str,
+}
+
+impl Report {
+    /// A report recording changes
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            ..Self::default()
+        }
+    }
+
+    /// Every change, in the order they were made
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Attributes the next changes to the given pass
+    pub fn start(&mut self, pass: &'static str) {
+        self.pass = pass;
+    }
+
+    /// Records a change of the current pass
+    ///
+    /// A pass run again on the same input makes the same changes, they are only recorded once.
+    pub fn add(&mut self, span: Option<Span>, description: String) {
+        if !self.enabled {
+            return;
+        }
+
+        let change = Change {
+            pass: self.pass,
+            span,
+            description,
+        };
+        if !self.changes.contains(&change) {
+            self.changes.push(change);
+        }
+    }
+}
+
+/// Lists the changes grouped by pass, in the order the passes first changed something
+///
+/// ```text
+/// const-fold: 1 change
+///     line 8: folded `1 + 2` into 3
+/// ```
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut passes: Vec<&str> = Vec::new();
+        for change in &self.changes {
+            if !passes.contains(&change.pass) {
+                passes.push(change.pass);
+            }
+        }
+
+        for pass in passes {
+            let changes: Vec<&Change> = self.changes.iter().filter(|c| c.pass == pass).collect();
+            let plural = if changes.len() == 1 { "" } else { "s" };
+            writeln!(f, "{pass}: {} change{plural}", changes.len())?;
+
+            for change in changes {
+                match change.span {
+                    Some(span) => writeln!(f, "    line {}: {}", span.line, change.description)?,
+                    None => writeln!(f, "    {}", change.description)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
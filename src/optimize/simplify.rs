@@ -0,0 +1,124 @@
+use super::{Pass, Report};
+use crate::{
+    span::Span,
+    syntax_tree::{ArOp, Arexpr, ArexprKind, SyntaxTree},
+};
+
+/// Applies algebraic identities to arithmetic expressions
+///
+/// Constants are moved to the right of `+` and `*`, then merged together, so that
+/// `(1 + x) + 2` becomes `x + 3`. Operations that do nothing, like `x + 0` or `x * 1`, are
+/// removed, and `x * 0` or `x - x` become `0` unless computing `x` could divide by zero.
+pub struct Simplify;
+
+impl Pass<SyntaxTree> for Simplify {
+    fn name(&self) -> &'static str {
+        "simplify"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        ast.visit_stmts_mut(&mut |stmt| {
+            let Some(arexpr) = stmt.arexpr_mut() else {
+                return false;
+            };
+
+            arexpr.visit_mut(&mut |arexpr| {
+                let (span, before) = (arexpr.span, arexpr.to_string());
+                let mut changed = false;
+
+                // a rewrite can enable another one, e.g. moving a constant then merging it
+                while let Some(simplified) = rewrite(arexpr) {
+                    *arexpr = simplified;
+                    changed = true;
+                }
+
+                if changed {
+                    report.add(Some(span), format!("simplified `{before}` into `{arexpr}`"));
+                }
+                changed
+            })
+        })
+    }
+}
+
+/// The simpler equivalent of a binary expression, if any
+///
+/// The operands are taken out of `arexpr` when there is one, it must then be replaced.
+fn rewrite(arexpr: &mut Arexpr) -> Option<Arexpr> {
+    let span = arexpr.span;
+    let ArexprKind::BinExpr { lhs, op, rhs } = &mut arexpr.kind else {
+        return None;
+    };
+    let op = *op;
+
+    match (num(lhs), op, num(rhs)) {
+        // left to const-fold
+        (Some(_), _, Some(_)) => None,
+        // constants go on the right, where they can be merged and used as an immediate
+        (Some(_), ArOp::Add | ArOp::Mul, None) => Some(Arexpr::new(
+            ArexprKind::BinExpr {
+                lhs: Box::new(take(rhs)),
+                op,
+                rhs: Box::new(take(lhs)),
+            },
+            span,
+        )),
+        (_, ArOp::Add | ArOp::Sub, Some(0)) | (_, ArOp::Mul | ArOp::Div, Some(1)) => {
+            Some(take(lhs))
+        }
+        (_, ArOp::Mul, Some(0)) if !lhs.may_divide_by_zero() => {
+            Some(Arexpr::new(ArexprKind::Num(0), span))
+        }
+        (None, ArOp::Sub, None) if lhs.same_as(rhs) && !lhs.may_divide_by_zero() => {
+            Some(Arexpr::new(ArexprKind::Num(0), span))
+        }
+        (None, _, Some(c2)) => reassociate(lhs, op, c2, span),
+        _ => None,
+    }
+}
+
+/// Merges the constants of `(x op c1) op c2`
+fn reassociate(lhs: &mut Arexpr, op: ArOp, c2: i32, span: Span) -> Option<Arexpr> {
+    let ArexprKind::BinExpr {
+        lhs: x,
+        op: inner,
+        rhs,
+    } = &mut lhs.kind
+    else {
+        return None;
+    };
+    let c1 = num(rhs)?;
+
+    let (op, c) = match (*inner, op) {
+        (ArOp::Add, ArOp::Add) => (ArOp::Add, c1.checked_add(c2)?),
+        (ArOp::Sub, ArOp::Add) => (ArOp::Add, c2.checked_sub(c1)?),
+        (ArOp::Add, ArOp::Sub) => (ArOp::Add, c1.checked_sub(c2)?),
+        (ArOp::Sub, ArOp::Sub) => (ArOp::Sub, c1.checked_add(c2)?),
+        (ArOp::Mul, ArOp::Mul) => (ArOp::Mul, c1.checked_mul(c2)?),
+        _ => return None,
+    };
+
+    let c = Arexpr::new(ArexprKind::Num(c), rhs.span.to(span));
+    Some(Arexpr::new(
+        ArexprKind::BinExpr {
+            lhs: Box::new(take(x)),
+            op,
+            rhs: Box::new(c),
+        },
+        span,
+    ))
+}
+
+/// The value of a literal
+fn num(arexpr: &Arexpr) -> Option<i32> {
+    match arexpr.kind {
+        ArexprKind::Num(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Takes an expression out of the tree, leaving a placeholder
+fn take(arexpr: &mut Arexpr) -> Arexpr {
+    let span = arexpr.span;
+    std::mem::replace(arexpr, Arexpr::new(ArexprKind::Num(0), span))
+}
@@ -0,0 +1,62 @@
+use super::{Pass, Report};
+use crate::syntax_tree::{ArOp, Arexpr, ArexprKind, SyntaxTree};
+
+/// Replaces multiplications and divisions by a power of two with shifts
+pub struct StrengthReduce;
+
+impl Pass<SyntaxTree> for StrengthReduce {
+    fn name(&self) -> &'static str {
+        "strength-reduce"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        ast.visit_stmts_mut(&mut |stmt| {
+            let Some(arexpr) = stmt.arexpr_mut() else {
+                return false;
+            };
+
+            arexpr.visit_mut(&mut |arexpr| {
+                let Some((shift, count)) = shift(arexpr) else {
+                    return false;
+                };
+
+                let before = arexpr.to_string();
+                let ArexprKind::BinExpr { op, rhs, .. } = &mut arexpr.kind else {
+                    unreachable!()
+                };
+                *op = shift;
+                rhs.kind = ArexprKind::Num(count);
+
+                report.add(
+                    Some(arexpr.span),
+                    format!("reduced `{before}` to `{arexpr}`"),
+                );
+                true
+            })
+        })
+    }
+}
+
+/// The shift equivalent to a multiplication or a division by a power of two, and its count
+fn shift(arexpr: &Arexpr) -> Option<(ArOp, i32)> {
+    let ArexprKind::BinExpr { lhs, op, rhs } = &arexpr.kind else {
+        return None;
+    };
+    // constants are computed by const-fold
+    if matches!(lhs.kind, ArexprKind::Num(_)) {
+        return None;
+    }
+    let ArexprKind::Num(n) = rhs.kind else {
+        return None;
+    };
+    if n < 2 || !(n as u32).is_power_of_two() {
+        return None;
+    }
+
+    let count = n.trailing_zeros() as i32;
+    match op {
+        ArOp::Mul => Some((ArOp::Shl, count)),
+        ArOp::Div => Some((ArOp::Shr, count)),
+        _ => None,
+    }
+}
@@ -0,0 +1,37 @@
+use super::{Pass, Report};
+use crate::{
+    cfg::Cfg,
+    syntax_tree::{Stmt, SyntaxTree},
+};
+
+/// Removes the lines that can never be executed
+///
+/// The blocks of the [`Cfg`] are reached from the first one, by going on to the next line or by a
+/// `GOTO`.
+pub struct Unreachable;
+
+impl Pass<SyntaxTree> for Unreachable {
+    fn name(&self) -> &'static str {
+        "unreachable"
+    }
+
+    fn run(&self, ast: &mut SyntaxTree, report: &mut Report) -> bool {
+        let cfg = Cfg::new(ast);
+        let unreached: Vec<usize> = cfg
+            .blocks
+            .iter()
+            .zip(cfg.reachable())
+            .filter(|(_, reached)| !reached)
+            .flat_map(|(block, _)| block.lines.clone())
+            .collect();
+
+        for index in &unreached {
+            let line = &mut ast.lines_mut()[*index];
+            let span = line.stmt().span;
+            report.add(Some(span), "dropped the line, never reached".to_string());
+            *line.stmt_mut() = Stmt::noop(span);
+        }
+
+        !unreached.is_empty()
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+use tbc::{
+    graphviz::{compile_dot, Digraph},
+    ir::Ir,
+    link,
+    optimize::Report,
+};
+use tiny_elf::asm::{AsAsm, Mnemonic, Program};
+
+use crate::cli::{Artifact, Cli, Emit};
+
+pub const PARSE_TREE_DOT_FILE: &str = "parse_tree.dot";
+pub const AST_DOT_FILE: &str = "ast.dot";
+pub const OST_DOT_FILE: &str = "ost.dot";
+pub const CFG_DOT_FILE: &str = "cfg.dot";
+pub const IR_FILE: &str = "dump.ir";
+pub const ASM_FILE: &str = "dump.asm";
+pub const BIN_FILE: &str = "dump.elf";
+pub const UNOPTIMIZED_ASM_FILE: &str = "udump.asm";
+pub const UNOPTIMIZED_BIN_FILE: &str = "udump.elf";
+
+/// Decides which files are written, and where
+pub struct Output {
+    dir: PathBuf,
+    bin: PathBuf,
+    emit: Emit,
+}
+
+impl Output {
+    /// Creates the output directory if needed
+    pub fn new(cli: &Cli) -> io::Result<Self> {
+        fs::create_dir_all(&cli.out_dir)?;
+
+        let mut emit = cli.emit.clone();
+        // running the program needs the executable
+        if cli.run {
+            emit.insert(Artifact::Elf);
+        }
+
+        Ok(Self {
+            dir: cli.out_dir.clone(),
+            bin: cli
+                .output
+                .clone()
+                .unwrap_or_else(|| cli.out_dir.join(BIN_FILE)),
+            emit,
+        })
+    }
+
+    pub fn emits(&self, artifact: Artifact) -> bool {
+        self.emit.contains(artifact)
+    }
+
+    /// Path of the optimized executable
+    pub fn bin(&self) -> &Path {
+        &self.bin
+    }
+
+    /// Writes a graph if the artifact is requested, and compiles it to an image
+    pub fn graph<G: Into<Digraph>>(
+        &self,
+        artifact: Artifact,
+        name: &str,
+        graph: impl FnOnce() -> G,
+    ) -> io::Result<()> {
+        if !self.emits(artifact) {
+            return Ok(());
+        }
+
+        let path = self.dir.join(name);
+        fs::write(&path, graph().into().to_string())?;
+
+        if let Err(e) = compile_dot(&path.to_string_lossy()) {
+            eprintln!("{e}");
+            eprintln!("Graphviz might not be installed. See https://graphviz.org/download/");
+        };
+
+        Ok(())
+    }
+
+    /// Writes the intermediate representation of a program if requested
+    pub fn ir(&self, ir: impl FnOnce() -> Ir) -> io::Result<()> {
+        if self.emits(Artifact::Ir) {
+            fs::write(self.dir.join(IR_FILE), ir().to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the assembly of a program if requested
+    pub fn asm(&self, artifact: Artifact, name: &str, program: &Program) -> io::Result<()> {
+        if self.emits(artifact) {
+            fs::write(self.dir.join(name), program.as_asm())?;
+        }
+
+        Ok(())
+    }
+
+    /// Links a program and writes the executable if requested
+    pub fn elf(&self, artifact: Artifact, path: &Path, program: &Program) -> io::Result<()> {
+        if !self.emits(artifact) {
+            return Ok(());
+        }
+
+        write_executable(path, &link(program))
+    }
+
+    /// Writes the unoptimized executable and assembly if requested
+    pub fn unoptimized(&self, program: &Program) -> io::Result<()> {
+        self.asm(Artifact::Unoptimized, UNOPTIMIZED_ASM_FILE, program)?;
+        self.elf(
+            Artifact::Unoptimized,
+            &self.dir.join(UNOPTIMIZED_BIN_FILE),
+            program,
+        )
+    }
+}
+
+/// Describes what the optimizations changed, then compares the unoptimized and optimized programs
+///
+/// Labels are not counted as instructions since they generate no code.
+///
+/// ```text
+/// const-fold: 1 change
+///     line 8: folded `1 + 2` into 3
+///
+/// instructions: 114 -> 77
+/// executable: 1024 -> 872 bytes
+/// ```
+pub fn opt_report(report: &Report, unoptimized: &Program, optimized: &Program) -> String {
+    format!(
+        "{report}\ninstructions: {} -> {}\nexecutable: {} -> {} bytes\n",
+        instruction_count(unoptimized),
+        instruction_count(optimized),
+        link(unoptimized).len(),
+        link(optimized).len(),
+    )
+}
+
+fn instruction_count(program: &Program) -> usize {
+    program
+        .instructions
+        .iter()
+        .filter(|inst| !matches!(inst, Mnemonic::Label(_)))
+        .count()
+}
+
+/// Writes a file and makes it executable by its owner
+pub fn write_executable(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    fs::write(path, bytes)?;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o100);
+    fs::set_permissions(path, permissions)
+}
+
+/// Writes an executable to a temporary file and runs it with the given input, capturing its output
+pub fn run_executable(name: &str, elf: &[u8], input: &[u8]) -> io::Result<process::Output> {
+    let path = env::temp_dir().join(format!("tbc-{}-{name}", process::id()));
+    write_executable(&path, elf)?;
+
+    let output = run(&path, input);
+    fs::remove_file(&path)?;
+    output
+}
+
+fn run(path: &Path, input: &[u8]) -> io::Result<process::Output> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // the program may exit without reading everything
+    let _ = child.stdin.take().unwrap().write_all(input);
+
+    child.wait_with_output()
+}
@@ -1,6 +1,10 @@
 mod renamed_rules;
 
-use pest::iterators::{Pair, Pairs};
+use pest::{
+    error::Error,
+    iterators::{Pair, Pairs},
+    Parser,
+};
 pub use renamed_rules::renamed_rules;
 
 use crate::graphviz::{Node, ToNode, ToNodes};
@@ -10,6 +14,54 @@ use crate::graphviz::{Node, ToNode, ToNodes};
 #[grammar = "parser/tinybasic.pest"]
 pub struct TbParser;
 
+/// Parses a whole file, recovering from syntax errors line by line
+///
+/// Each line containing an error is blanked out and the file is parsed again, so that every broken
+/// line is reported at once. Blanking keeps the byte length of the line, positions of the other
+/// lines are thus unchanged.
+///
+/// Returns the source without the broken lines along with the errors.
+pub fn recover(source: &str) -> (String, Vec<Error<Rule>>) {
+    let mut source = source.to_string();
+    let mut errors = Vec::new();
+
+    while let Err(err) = TbParser::parse(Rule::file, &source) {
+        let pos = match err.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        errors.push(err);
+
+        if !blank_line(&mut source, pos) {
+            break;
+        }
+    }
+
+    (source, errors)
+}
+
+/// Replaces the line containing `pos` with spaces
+///
+/// Returns whether something was blanked.
+fn blank_line(source: &mut String, pos: usize) -> bool {
+    let start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[pos..].find('\n').map_or(source.len(), |i| pos + i);
+
+    let line = &source[start..end];
+    if line.trim().is_empty() {
+        return false;
+    }
+
+    // spaces are ASCII, so this keeps the byte length of multi-bytes characters
+    let blank: String = line
+        .bytes()
+        .map(|b| if b == b'\r' { '\r' } else { ' ' })
+        .collect();
+    source.replace_range(start..end, &blank);
+
+    true
+}
+
 impl ToNodes for Pairs<'_, Rule> {
     fn to_nodes(&self) -> Vec<Node> {
         let pairs = self.clone();
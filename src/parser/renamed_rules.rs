@@ -14,6 +14,12 @@ pub fn renamed_rules(rule: &Rule) -> String {
         R::ident => "identifier".into(),
         R::expr => "expression".into(),
         R::cond => "condition".into(),
+        R::string => "string".into(),
+        R::escape => "escape sequence".into(),
+        R::quote => "\"\"".into(),
+        R::text => "string content".into(),
+        R::NEWLINE => "new line".into(),
+        R::EOI => "end of file".into(),
         r => format!("{:?}", r),
     }
 }
@@ -0,0 +1,270 @@
+//! Interactive session in the classic Tiny BASIC style.
+//!
+//! A numbered line is stored in the program, replacing the line with the same number, while a bare
+//! statement is executed immediately. A number alone deletes its line.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, BufRead, StdinLock, Stdout, Write},
+    process::Command,
+};
+
+use pest::{iterators::Pair, Parser};
+use tbc::{
+    assemble, check,
+    error::{CompileError, Diagnostic, RuntimeError},
+    interpreter::{Flow, Interpret, Interpreter},
+    link, optimize,
+    optimize::Passes,
+    parse,
+    parser::{Rule, TbParser},
+    syntax_tree::{Line, ParseContext},
+};
+
+use crate::output::write_executable;
+
+const PROMPT: &str = "> ";
+/// Name given to the entered line in diagnostics
+const INPUT_NAME: &str = "<input>";
+/// Name given to the stored program in diagnostics
+const PROGRAM_NAME: &str = "<program>";
+
+const HELP: &str = "\
+<number> <statement>  store a line, replacing the previous one with this number
+<number>              delete a line
+<statement>           execute a statement immediately
+LIST                  show the program
+RUN                   run the program with the interpreter, from fresh variables
+RUN NATIVE            compile the program and run the executable
+NEW                   delete the program and every variable
+SAVE <file>           write the program to a file
+LOAD <file>           replace the program with the content of a file
+BYE                   quit";
+
+pub struct Repl {
+    /// Statements of the program by line number
+    lines: BTreeMap<usize, String>,
+    /// Variables of the statements executed immediately and of the last run, it also reads the
+    /// commands so that `INPUT` and the prompt share the buffered input
+    interpreter: Interpreter<StdinLock<'static>, Stdout>,
+    passes: Passes,
+}
+
+impl Repl {
+    pub fn new(passes: Passes) -> Self {
+        Self {
+            lines: BTreeMap::new(),
+            interpreter: Interpreter::new(io::stdin().lock(), io::stdout()),
+            passes,
+        }
+    }
+
+    /// Reads and executes commands until the end of the input or `BYE`
+    pub fn run(&mut self) -> io::Result<()> {
+        println!("Tiny BASIC, type HELP for the list of commands");
+
+        loop {
+            print!("{PROMPT}");
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            if self.interpreter.reader().read_line(&mut input)? == 0 {
+                println!();
+                return Ok(());
+            }
+
+            let input = input.trim();
+            let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+            let argument = argument.trim();
+
+            match command {
+                "" => {}
+                "BYE" => return Ok(()),
+                "HELP" => println!("{HELP}"),
+                "LIST" => {
+                    for (number, stmt) in &self.lines {
+                        println!("{number} {stmt}");
+                    }
+                }
+                "NEW" => {
+                    self.lines.clear();
+                    self.interpreter.clear();
+                }
+                "RUN" if argument.is_empty() => self.run_interpreted()?,
+                "RUN" if argument == "NATIVE" => self.run_native()?,
+                "SAVE" if !argument.is_empty() => fs::write(argument, self.source())?,
+                "LOAD" if !argument.is_empty() => match fs::read_to_string(argument) {
+                    Ok(source) => self.load(&source, argument),
+                    Err(e) => eprintln!("{e}"),
+                },
+                _ => self.enter(input)?,
+            }
+        }
+    }
+
+    /// Stores, deletes or executes a line depending on whether it starts with a number
+    fn enter(&mut self, input: &str) -> io::Result<()> {
+        let line = match TbParser::parse(Rule::line, input) {
+            Ok(mut pairs) => pairs.next().unwrap(),
+            Err(e) => {
+                eprint!("{}", Diagnostic::from(e).render(INPUT_NAME, input));
+                return Ok(());
+            }
+        };
+
+        let numbered = line
+            .clone()
+            .into_inner()
+            .next()
+            .is_some_and(|pair| pair.as_rule() == Rule::number);
+        let stmt = statement(&line);
+
+        let line = match Line::from_pair(line, &mut ParseContext::default()) {
+            Ok(line) => line,
+            Err(e) => {
+                eprint!("{}", Diagnostic::from(&e).render(INPUT_NAME, input));
+                return Ok(());
+            }
+        };
+
+        if numbered {
+            match stmt {
+                Some(stmt) => self.lines.insert(line.number(), stmt),
+                None => self.lines.remove(&line.number()),
+            };
+            return Ok(());
+        }
+
+        match line.stmt().interpret(&mut self.interpreter) {
+            Ok(Flow::Goto(_)) => eprintln!("GOTO only works in a program, store it then RUN"),
+            Ok(_) => {}
+            Err(RuntimeError::Io(e)) => return Err(e),
+            Err(e) => eprint!("{}", Diagnostic::from(&e).render(INPUT_NAME, input)),
+        }
+
+        io::stdout().flush()
+    }
+
+    /// Replaces the program with a whole file, numbering lines like the compiler does
+    fn load(&mut self, source: &str, path: &str) {
+        let (_, errors) = tbc::parser::recover(source);
+        if !errors.is_empty() {
+            for error in errors {
+                eprint!("{}", Diagnostic::from(error).render(path, source));
+            }
+            return;
+        }
+
+        let file = TbParser::parse(Rule::file, source).unwrap().next().unwrap();
+        let mut ctx = ParseContext::default();
+        let mut lines = BTreeMap::new();
+
+        for pair in file
+            .into_inner()
+            .filter(|pair| pair.as_rule() == Rule::line)
+        {
+            let stmt = statement(&pair);
+
+            match Line::from_pair(pair, &mut ctx) {
+                Ok(line) => {
+                    if let Some(stmt) = stmt {
+                        lines.insert(line.number(), stmt);
+                    }
+                }
+                Err(e) => {
+                    eprint!("{}", Diagnostic::from(&e).render(path, source));
+                    return;
+                }
+            }
+        }
+
+        self.lines = lines;
+    }
+
+    /// The program as a Tiny BASIC file
+    fn source(&self) -> String {
+        self.lines
+            .iter()
+            .map(|(number, stmt)| format!("{number} {stmt}\n"))
+            .collect()
+    }
+
+    /// Compiles the stored program to a syntax tree, reporting every diagnostic
+    fn compile(&self, source: &str) -> Option<tbc::Parsed> {
+        let result = parse(source).and_then(|parsed| {
+            for diagnostic in check(&parsed)? {
+                eprint!("{}", diagnostic.render(PROGRAM_NAME, source));
+            }
+            Ok(parsed)
+        });
+
+        match result {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                report(&e, source);
+                None
+            }
+        }
+    }
+
+    /// Runs the program with fresh variables, which are kept afterwards to be printed
+    fn run_interpreted(&mut self) -> io::Result<()> {
+        let source = self.source();
+        let Some(parsed) = self.compile(&source) else {
+            return Ok(());
+        };
+
+        let ost = optimize(parsed.ast, &self.passes);
+        // the program is checked alone, it cannot read the variables of previous statements
+        self.interpreter.clear();
+
+        match self.interpreter.run(&ost) {
+            Ok(()) => Ok(()),
+            Err(RuntimeError::Io(e)) => Err(e),
+            Err(e) => {
+                eprint!("{}", Diagnostic::from(&e).render(PROGRAM_NAME, &source));
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles the program to a temporary executable and runs it
+    fn run_native(&mut self) -> io::Result<()> {
+        let source = self.source();
+        let Some(parsed) = self.compile(&source) else {
+            return Ok(());
+        };
+
+        let ost = optimize(parsed.ast, &self.passes);
+        let program = assemble(&ost, &parsed.symbol_table, &self.passes);
+
+        let path = env::temp_dir().join(format!("tbc-repl-{}.elf", std::process::id()));
+        write_executable(&path, &link(&program))?;
+
+        let status = Command::new(&path).status();
+        fs::remove_file(&path)?;
+
+        match status {
+            Ok(status) if !status.success() => println!("{status}"),
+            Ok(_) => {}
+            Err(e) => eprintln!("{e}"),
+        }
+
+        Ok(())
+    }
+}
+
+/// The source of the statement of a `line` pair, if any
+fn statement(line: &Pair<'_, Rule>) -> Option<String> {
+    line.clone()
+        .into_inner()
+        .find(|pair| pair.as_rule() == Rule::stmt)
+        .map(|pair| pair.as_str().trim().to_string())
+}
+
+fn report(error: &CompileError, source: &str) {
+    for diagnostic in error.diagnostics() {
+        eprint!("{}", diagnostic.render(PROGRAM_NAME, source));
+    }
+}
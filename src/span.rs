@@ -0,0 +1,46 @@
+use std::fmt::Display;
+
+/// A location in the source code
+///
+/// It keeps both the byte offsets, to slice the source, and the human readable position of its
+/// start.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offset of the start
+    pub start: usize,
+    /// Byte offset of the end (exclusive)
+    pub end: usize,
+    /// Line of the start, starting at 1
+    pub line: usize,
+    /// Column of the start, starting at 1
+    pub col: usize,
+}
+
+impl Span {
+    /// Creates a span going from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(value: pest::Span<'_>) -> Self {
+        let (line, col) = value.start_pos().line_col();
+
+        Self {
+            start: value.start(),
+            end: value.end(),
+            line,
+            col,
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
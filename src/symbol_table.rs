@@ -1,7 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use tiny_elf::asm::{Operand, Program};
 
+use crate::span::Span;
+
 /// The symbol table
 ///
 /// Note there is no parent table because we don't need it in TinyBASIC, everything is global.
@@ -15,8 +17,8 @@ pub struct SymbolTable {
 }
 
 impl SymbolTable {
-    /// Creates a new symbol in the table
-    pub fn insert(&mut self, name: &str, ty: Type) {
+    /// Creates a new symbol in the table, `span` being where it is declared
+    pub fn insert(&mut self, name: &str, ty: Type, span: Span) {
         // This basically implements shadowing
         // Meaning you can declare a variable with the same name
         if self.get(name).is_some() {
@@ -24,7 +26,7 @@ impl SymbolTable {
         }
 
         self.symbols
-            .insert(name.into(), Symbol::new(ty, self.current_address));
+            .insert(name.into(), Symbol::new(ty, self.current_address, span));
         self.current_address += 8;
     }
 
@@ -33,6 +35,28 @@ impl SymbolTable {
         self.symbols.get(name)
     }
 
+    /// Iterates over every symbol with its name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.symbols.iter()
+    }
+
+    /// A copy of this table with only the given variables, packed at the start of the stack
+    ///
+    /// Variables keep their relative order.
+    pub fn retain(&self, names: &HashSet<&str>) -> Self {
+        let mut symbols: Vec<(&String, &Symbol)> = self
+            .iter()
+            .filter(|(name, _)| names.contains(name.as_str()))
+            .collect();
+        symbols.sort_by_key(|(_, symbol)| symbol.address);
+
+        let mut table = Self::default();
+        for (name, symbol) in symbols {
+            table.insert(name, symbol.ty, symbol.span);
+        }
+        table
+    }
+
     /// The size to allocate on the stack for every symbol to fit
     ///
     /// Note that string are note on the stack but in the data section.
@@ -45,7 +69,7 @@ impl SymbolTable {
 
     /// Puts the variable with the given name in the [`Rbx`](tiny_elf::asm::Register::Rbx) register
     ///
-    /// This is not usually part of a symbol table. However, this is convenient since stack access 
+    /// This is not usually part of a symbol table. However, this is convenient since stack access
     /// is so complicated because pointer arithmetic is not implemented.
     pub fn access(&self, name: &str, program: Program) -> Program {
         use tiny_elf::asm::{Mnemonic::*, Register::*};
@@ -61,7 +85,7 @@ impl SymbolTable {
     /// [`Mov`](tiny_elf::asm::Mnemonic::Mov) the given operand into the variable with the given
     /// name
     ///
-    /// This is not usually part of a symbol table. However, this is convenient since stack access 
+    /// This is not usually part of a symbol table. However, this is convenient since stack access
     /// is so complicated because pointer arithmetic is not implemented.
     pub fn write(&self, name: &str, value: Operand, program: Program) -> Program {
         use tiny_elf::asm::{Mnemonic::*, Register::*};
@@ -80,11 +104,18 @@ pub struct Symbol {
     ty: Type,
     /// Its address on the stack
     address: u32,
+    /// Where it is first declared
+    span: Span,
 }
 
 impl Symbol {
-    fn new(ty: Type, address: u32) -> Self {
-        Self { ty, address }
+    fn new(ty: Type, address: u32, span: Span) -> Self {
+        Self { ty, address, span }
+    }
+
+    /// Gets where this symbol is first declared
+    pub fn span(&self) -> Span {
+        self.span
     }
 
     /// Gets the start address of this symbol
@@ -102,7 +133,7 @@ impl Symbol {
     }
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub enum Type {
     #[default]
     Int,
@@ -1,11 +1,18 @@
-use pest::iterators::Pairs;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::{Read, Write},
+    num::ParseIntError,
+    str::FromStr,
+};
+
+use pest::iterators::{Pair, Pairs};
 
-use self::line::Line;
 use crate::{
-    error::SyntaxError,
-    generate::Generate,
+    check::{Check, Checker},
+    error::{RuntimeError, SyntaxError},
     graphviz::{Node, ToNode},
-    optimize::Optimize,
+    interpreter::{Flow, Interpret, Interpreter},
+    ir::{Builder, Lower},
     parser::Rule,
     symbol_table::SymbolTable,
 };
@@ -20,17 +27,75 @@ pub use expr::*;
 pub use line::*;
 pub use stmt::*;
 
+/// State of the conversion from the parse tree
+///
+/// A new one must be used for each program.
+#[derive(Default, Debug)]
+pub struct ParseContext {
+    /// Every variable declared so far
+    pub symbol_table: SymbolTable,
+    /// Number of the previous line, line numbers must be increasing
+    last_line: usize,
+}
+
 #[derive(Debug)]
 pub struct SyntaxTree {
     lines: Vec<Line>,
 }
 
 impl SyntaxTree {
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Mutable access to the lines, for optimization passes
+    pub fn lines_mut(&mut self) -> &mut Vec<Line> {
+        &mut self.lines
+    }
+
+    /// Calls `f` on every statement, including the ones nested in `IF`
+    ///
+    /// Returns whether any call of `f` did.
+    pub fn visit_stmts_mut(&mut self, f: &mut impl FnMut(&mut Stmt) -> bool) -> bool {
+        let mut changed = false;
+
+        for line in &mut self.lines {
+            changed |= line.stmt_mut().visit_mut(f);
+        }
+
+        changed
+    }
+
+    /// The lines targeted by a `GOTO`, without duplicates
+    pub fn targets(&self) -> BTreeSet<usize> {
+        self.lines
+            .iter()
+            .flat_map(|line| line.stmt().targets())
+            .map(|line| line as usize)
+            .collect()
+    }
+
+    /// Every variable read or written by the program
+    pub fn variables(&self) -> HashSet<&str> {
+        self.lines
+            .iter()
+            .flat_map(|line| {
+                let mut variables = line.stmt().reads();
+                variables.extend(line.stmt().writes());
+                variables
+            })
+            .collect()
+    }
+
+    /// Converts the parse tree to a syntax tree
+    ///
+    /// Lines with errors are skipped so that every error is returned at once.
     pub fn from_pairs(
         mut value: Pairs<'_, Rule>,
-        symbol_table: &mut SymbolTable,
-    ) -> Result<Self, SyntaxError> {
+        ctx: &mut ParseContext,
+    ) -> Result<Self, Vec<SyntaxError>> {
         let mut lines = vec![];
+        let mut errors = vec![];
 
         let file = value.next().expect("Empty parse tree");
 
@@ -40,13 +105,31 @@ impl SyntaxTree {
             if line.as_rule() == Rule::EOI {
                 break;
             }
-            lines.push(Line::from_pair(line, symbol_table)?)
+            match Line::from_pair(line, ctx) {
+                Ok(line) => lines.push(line),
+                Err(err) => errors.push(err),
+            }
         }
 
-        Ok(Self { lines })
+        if errors.is_empty() {
+            Ok(Self { lines })
+        } else {
+            Err(errors)
+        }
     }
 }
 
+/// Parses a `number` pair, reporting its location on failure
+pub(crate) fn parse_number<T>(pair: &Pair<'_, Rule>) -> Result<T, SyntaxError>
+where
+    T: FromStr<Err = ParseIntError>,
+{
+    pair.as_str()
+        .trim()
+        .parse()
+        .map_err(|e| SyntaxError::ParseIntError(e, pair.as_span().into()))
+}
+
 impl ToNode for SyntaxTree {
     fn to_node(&self) -> Node {
         let mut node = Node::new("program");
@@ -59,90 +142,59 @@ impl ToNode for SyntaxTree {
     }
 }
 
-impl Generate for SyntaxTree {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Mnemonic::*, Register::*};
-
-        // R15 is used as the stack base pointer
-        // allocate stack space
-        let mut program = program
-            .add(Mov(R15, Rsp.into()))
-            .add(Sub(Rsp, (symbol_table.size() as i32).into()));
+impl Check for SyntaxTree {
+    fn check(&self, checker: &mut Checker) {
+        checker.lines.extend(
+            self.lines
+                .iter()
+                .filter(|line| !line.is_empty())
+                .map(Line::number),
+        );
 
         for line in &self.lines {
-            program = line.generate(program, symbol_table);
+            line.check(checker);
         }
+    }
+}
+
+impl Lower for SyntaxTree {
+    type Output = ();
 
-        // don't execute function
-        let program = program.add(Jmp("exit".into()));
-
-        let program = program
-            .func("printn")
-            // init
-            .add(Mov(Rax, Rsi.into()))
-            .add(Xor(Rcx, Rcx.into()))
-            .add(Mov(Rbx, 10.into()))
-            // loop over digit and store on stack
-            .add(Jmp("printn_inner_cond".into()))
-            .label("printn_inner")
-            .add(Xor(Rdx, Rdx.into()))
-            .add(IDiv(Rbx))
-            .add(Add(Rdx, ('0' as i32).into()))
-            .add(Push(Rdx.into()))
-            .add(Inc(Rcx))
-            .label("printn_inner_cond")
-            .add(Cmp(Rax, 10.into()))
-            .add(Jge("printn_inner".into()))
-            // handle last digit
-            .add(Add(Rax, ('0' as i32).into()))
-            .add(Push(Rax.into()))
-            .add(Inc(Rcx))
-            // setup print params
-            .add(Mov(Rsi, Rsp.into()))
-            .add(IMul(Rcx, 8.into()))
-            .add(Mov(Rdx, Rcx.into()))
-            .add(Call("print".into()))
-            .func_end();
-
-        let program = program
-            .func("print")
-            .add(Mov(Rax, 1.into()))
-            .add(Mov(Rdi, 1.into()))
-            .add(Syscall)
-            .func_end();
-
-        let program = program
-            .func("read")
-            .add(Mov(Rax, 0.into()))
-            .add(Mov(Rdi, 0.into()))
-            .add(Syscall)
-            .func_end();
-
-        program
-            .label("exit")
-            .add(Mov(Rax, 60.into()))
-            .add(Mov(Rdi, 0.into()))
-            .add(Syscall)
+    fn lower(&self, builder: &mut Builder) {
+        for line in &self.lines {
+            line.lower(builder);
+        }
     }
 }
 
-impl Optimize for SyntaxTree {
-    fn optimize(mut self) -> Self {
-        let mut lines = Vec::new();
+impl Interpret for SyntaxTree {
+    type Output = ();
 
-        for line in self.lines {
-            let line = line.optimize();
-            if !line.is_empty() {
-                lines.push(line);
-            }
+    fn interpret<R: Read, W: Write>(
+        &self,
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<(), RuntimeError> {
+        // index of each line number, first one wins for unnumbered lines
+        let mut targets = BTreeMap::new();
+        for (index, line) in self.lines.iter().enumerate().rev() {
+            targets.insert(line.number() as u32, index);
         }
 
-        self.lines = lines;
+        let mut index = 0;
+
+        while let Some(line) = self.lines.get(index) {
+            index = match line.stmt().interpret(interpreter)? {
+                Flow::Next => index + 1,
+                // like the compiled program, a target removed by optimizations goes on to the
+                // next line, and past the last one the program ends
+                Flow::Goto(number) => targets
+                    .range(number..)
+                    .next()
+                    .map_or(self.lines.len(), |(_, index)| *index),
+                Flow::End => break,
+            };
+        }
 
-        self
+        Ok(())
     }
 }
@@ -1,10 +1,19 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
+
 use pest::iterators::Pair;
 
 use crate::{
-    generate::Generate,
+    check::{Check, Checker},
+    error::{RuntimeError, SyntaxError},
     graphviz::{Node, ToNode},
+    interpreter::{Interpret, Interpreter},
+    ir::{Builder, Lower, Value},
     parser::Rule,
-    symbol_table::SymbolTable,
+    span::Span,
+    syntax_tree::parse_number,
 };
 
 mod cond_operand;
@@ -13,77 +22,119 @@ mod relop;
 pub use cond_operand::*;
 pub use relop::*;
 
-/// Used for assembly jumps
-pub static mut COND_COUNT: usize = 0;
-
 #[derive(Debug)]
 pub struct Cond {
     lhs: CondOperand,
     relop: RelOp,
     rhs: CondOperand,
+    pub span: Span,
 }
 
 impl Cond {
-    pub fn try_execute(&self) -> Result<bool, ()> {
-        match (&self.lhs, &self.rhs) {
-            (CondOperand::Num(lhs), CondOperand::Num(rhs)) => Ok(self.relop.execute(*lhs, *rhs)),
+    pub fn from_pair(value: Pair<'_, Rule>) -> Result<Self, SyntaxError> {
+        debug_assert_eq!(value.as_rule(), Rule::cond);
+        let span = value.as_span().into();
+        let mut inner = value.into_inner();
+
+        let lhs = operand(inner.next().unwrap())?;
+        let relop = inner.next().unwrap().into();
+        let rhs = operand(inner.next().unwrap())?;
+
+        Ok(Self {
+            lhs,
+            relop,
+            rhs,
+            span,
+        })
+    }
+
+    pub fn relop(&self) -> RelOp {
+        self.relop
+    }
+
+    /// Both operands, left one first
+    pub fn operands(&self) -> [&CondOperand; 2] {
+        [&self.lhs, &self.rhs]
+    }
+
+    /// Mutable access to both operands, for optimization passes
+    pub fn operands_mut(&mut self) -> [&mut CondOperand; 2] {
+        [&mut self.lhs, &mut self.rhs]
+    }
+
+    /// The variables compared by this condition
+    pub fn variables(&self) -> Vec<&str> {
+        [&self.lhs, &self.rhs]
+            .into_iter()
+            .filter_map(|operand| match &operand.kind {
+                CondOperandKind::Ident(name) => Some(name.as_str()),
+                CondOperandKind::Num(_) => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn try_execute(&self) -> Result<bool, ()> {
+        match (&self.lhs.kind, &self.rhs.kind) {
+            (CondOperandKind::Num(lhs), CondOperandKind::Num(rhs)) => {
+                Ok(self.relop.execute(*lhs, *rhs))
+            }
             _ => Err(()),
         }
     }
 }
 
-impl From<Pair<'_, Rule>> for Cond {
-    fn from(value: Pair<'_, Rule>) -> Self {
-        debug_assert_eq!(value.as_rule(), Rule::cond);
-        let mut inner = value.into_inner();
-
-        let lhs = inner.next().unwrap();
-        let lhs = if lhs.as_rule() == Rule::number {
-            lhs.as_str().trim().parse::<i32>().unwrap().into()
-        } else {
-            lhs.as_str().trim().into()
-        };
+/// Converts a `number` or `ident` pair to a [`CondOperand`]
+fn operand(pair: Pair<'_, Rule>) -> Result<CondOperand, SyntaxError> {
+    let kind = if pair.as_rule() == Rule::number {
+        parse_number::<i32>(&pair)?.into()
+    } else {
+        pair.as_str().trim().into()
+    };
 
-        let relop = inner.next().unwrap().into();
-
-        let rhs = inner.next().unwrap();
-        let rhs = if rhs.as_rule() == Rule::number {
-            rhs.as_str().trim().parse::<i32>().unwrap().into()
-        } else {
-            rhs.as_str().trim().into()
-        };
+    Ok(CondOperand::new(kind, pair.as_span().into()))
+}
 
-        Self { lhs, relop, rhs }
+impl Display for Cond {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.relop, self.rhs)
     }
 }
 
 impl ToNode for Cond {
     fn to_node(&self) -> crate::graphviz::Node {
         Node::new(&self.relop.to_string())
+            .tooltip(&self.span.to_string())
             .add(self.lhs.to_node())
             .add(self.rhs.to_node())
     }
 }
 
-impl Generate for Cond {
-    fn generate(
+impl Check for Cond {
+    fn check(&self, checker: &mut Checker) {
+        self.lhs.check(checker);
+        self.rhs.check(checker);
+    }
+}
+
+impl Lower for Cond {
+    /// The operands and the operator to compare them with
+    type Output = (Value, RelOp, Value);
+
+    fn lower(&self, builder: &mut Builder) -> Self::Output {
+        (self.lhs.lower(builder), self.relop, self.rhs.lower(builder))
+    }
+}
+
+impl Interpret for Cond {
+    type Output = bool;
+
+    fn interpret<R: Read, W: Write>(
         &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Mnemonic::*, Register::*};
-
-        let program = self
-            .lhs
-            .generate(program, symbol_table)
-            .add(Mov(R8, Rbx.into()));
-        let program = self
-            .rhs
-            .generate(program, symbol_table)
-            .add(Mov(R9, Rbx.into()));
-
-        let program = program.add(Cmp(R8, R9.into()));
-
-        self.relop.generate(program, symbol_table)
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<bool, RuntimeError> {
+        let lhs = self.lhs.interpret(interpreter)?;
+        let rhs = self.rhs.interpret(interpreter)?;
+
+        Ok(self.relop.execute(lhs, rhs))
     }
 }
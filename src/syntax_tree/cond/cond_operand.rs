@@ -1,47 +1,97 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
+
 use crate::{
-    generate::Generate,
+    check::{Check, Checker},
+    error::RuntimeError,
     graphviz::{Node, ToNode},
-    symbol_table::SymbolTable,
+    interpreter::{Interpret, Interpreter},
+    ir::{Builder, Lower, Value},
+    span::Span,
 };
 
 /// The operand of a condition
 #[derive(Debug)]
-pub enum CondOperand {
+pub struct CondOperand {
+    pub kind: CondOperandKind,
+    pub span: Span,
+}
+
+/// The different kinds of [`CondOperand`]
+#[derive(Debug)]
+pub enum CondOperandKind {
     Num(i32),
     Ident(String),
 }
 
-impl From<i32> for CondOperand {
+impl CondOperand {
+    pub fn new(kind: CondOperandKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl From<i32> for CondOperandKind {
     fn from(value: i32) -> Self {
         Self::Num(value)
     }
 }
 
-impl From<&str> for CondOperand {
+impl From<&str> for CondOperandKind {
     fn from(value: &str) -> Self {
         Self::Ident(value.to_string())
     }
 }
 
+impl Display for CondOperand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            CondOperandKind::Num(n) => write!(f, "{n}"),
+            CondOperandKind::Ident(ident) => write!(f, "{ident}"),
+        }
+    }
+}
+
 impl ToNode for CondOperand {
     fn to_node(&self) -> Node {
-        match self {
-            CondOperand::Num(n) => n.to_node(),
-            CondOperand::Ident(ident) => ident.to_node(),
+        match &self.kind {
+            CondOperandKind::Num(n) => n.to_node(),
+            CondOperandKind::Ident(ident) => ident.to_node(),
         }
+        .tooltip(&self.span.to_string())
     }
 }
 
-impl Generate for CondOperand {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Mnemonic::*, Register::*};
-        match self {
-            CondOperand::Num(n) => program.add(Mov(Rbx, n.to_owned().into())),
-            CondOperand::Ident(ident) => symbol_table.access(ident, program),
+impl Check for CondOperand {
+    fn check(&self, checker: &mut Checker) {
+        if let CondOperandKind::Ident(ident) = &self.kind {
+            checker.check_variable(ident, self.span);
+        }
+    }
+}
+
+impl Lower for CondOperand {
+    type Output = Value;
+
+    fn lower(&self, _: &mut Builder) -> Value {
+        match &self.kind {
+            CondOperandKind::Num(n) => Value::Const(*n),
+            CondOperandKind::Ident(ident) => Value::Var(ident.clone()),
         }
     }
 }
+
+impl Interpret for CondOperand {
+    type Output = i32;
+
+    fn interpret<R: Read, W: Write>(
+        &self,
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<i32, RuntimeError> {
+        Ok(match &self.kind {
+            CondOperandKind::Num(n) => *n,
+            CondOperandKind::Ident(ident) => interpreter.variable(ident),
+        })
+    }
+}
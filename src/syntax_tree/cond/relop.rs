@@ -2,11 +2,10 @@ use std::fmt::Display;
 
 use pest::iterators::Pair;
 
-use super::COND_COUNT;
-use crate::{generate::Generate, parser::Rule, symbol_table::SymbolTable};
+use crate::parser::Rule;
 
 /// All relational operator
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RelOp {
     Eq,
     Ne,
@@ -61,28 +60,3 @@ impl From<Pair<'_, Rule>> for RelOp {
         }
     }
 }
-
-impl Generate for RelOp {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        _: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Memory, Mnemonic::*};
-
-        let then_label: Memory = format!("then{}", unsafe {
-            COND_COUNT += 1;
-            COND_COUNT
-        })
-        .into();
-
-        program.add(match self {
-            RelOp::Eq => Je(then_label),
-            RelOp::Ne => Jne(then_label),
-            RelOp::Ge => Jge(then_label),
-            RelOp::Gt => Jg(then_label),
-            RelOp::Le => Jle(then_label),
-            RelOp::Lt => Jl(then_label),
-        })
-    }
-}
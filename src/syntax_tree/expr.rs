@@ -1,57 +1,113 @@
+use std::fmt::Display;
+
 use pest::iterators::Pair;
 
 use crate::{
+    check::{Check, Checker},
+    error::SyntaxError,
     graphviz::{Node, ToNode},
-    optimize::Optimize,
     parser::Rule,
-    symbol_table::SymbolTable,
+    span::Span,
+    syntax_tree::ParseContext,
 };
 
 mod arexpr;
 pub use arexpr::*;
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+/// The different kinds of [`Expr`]
+#[derive(Debug)]
+pub enum ExprKind {
     String(String),
     /// Arithmetic expression
     Arexpr(Arexpr),
 }
 
 impl Expr {
-    pub fn from_pair(value: Pair<'_, Rule>, symbol_table: &mut SymbolTable) -> Self {
+    pub fn from_pair(value: Pair<'_, Rule>, ctx: &mut ParseContext) -> Result<Self, SyntaxError> {
+        let span = value.as_span().into();
         let inner = value.into_inner().next().unwrap();
 
-        match inner.as_rule() {
-            Rule::string => Expr::String(strip(inner.as_str())),
-            Rule::arexpr => Expr::Arexpr(Arexpr::from_pair(inner, symbol_table)),
+        let kind = match inner.as_rule() {
+            Rule::string => ExprKind::String(unescape(inner)),
+            Rule::arexpr => ExprKind::Arexpr(Arexpr::from_pair(inner, ctx)?),
             rule => unreachable!("Expected expression, found {:?}", rule),
+        };
+
+        Ok(Self { kind, span })
+    }
+}
+
+/// Writes the expression back as source, strings with their escape sequences
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ExprKind::String(str) => {
+                write!(f, "\"")?;
+                for c in str.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if c.is_ascii_control() => write!(f, "\\x{:02X}", c as u8)?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            ExprKind::Arexpr(arexpr) => write!(f, "{arexpr}"),
         }
     }
 }
 
 impl ToNode for Expr {
     fn to_node(&self) -> crate::graphviz::Node {
-        match self {
-            Self::String(str) => Node::new(&format!(r#""{str}""#)),
-            Self::Arexpr(arexpr) => arexpr.to_node(),
+        match &self.kind {
+            ExprKind::String(str) => {
+                Node::new(&format!(r#""{str}""#)).tooltip(&self.span.to_string())
+            }
+            ExprKind::Arexpr(arexpr) => arexpr.to_node(),
         }
     }
 }
 
-impl Optimize for Expr {
-    fn optimize(self) -> Self {
-        match self {
-            Expr::String(_) => self,
-            Expr::Arexpr(arexpr) => Expr::Arexpr(arexpr.optimize()),
+impl Check for Expr {
+    fn check(&self, checker: &mut Checker) {
+        match &self.kind {
+            ExprKind::String(_) => {}
+            ExprKind::Arexpr(arexpr) => arexpr.check(checker),
         }
     }
 }
 
-fn strip(str: &str) -> String {
-    str.trim()
-        .strip_suffix('"')
-        .unwrap()
-        .strip_prefix('"')
-        .unwrap()
-        .to_string()
+/// Builds the actual value of a string literal, processing its escape sequences
+fn unescape(string: Pair<'_, Rule>) -> String {
+    let mut value = String::new();
+
+    for part in string.into_inner() {
+        match part.as_rule() {
+            Rule::text => value.push_str(part.as_str()),
+            Rule::quote => value.push('"'),
+            Rule::escape => match &part.as_str()[1..] {
+                "n" => value.push('\n'),
+                "t" => value.push('\t'),
+                "\"" => value.push('"'),
+                "\\" => value.push('\\'),
+                hex => {
+                    // the grammar only allows ASCII bytes
+                    let byte = u8::from_str_radix(&hex[1..], 16).unwrap();
+                    value.push(byte as char);
+                }
+            },
+            rule => unreachable!("Expected string part, found {:?}", rule),
+        }
+    }
+
+    value
 }
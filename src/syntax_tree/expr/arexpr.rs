@@ -1,31 +1,47 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
 
 use pest::{iterators::Pair, pratt_parser::PrattParser};
 
 use crate::{
-    generate::Generate,
+    check::{Check, Checker},
+    error::{RuntimeError, SyntaxError},
     graphviz::{Node, ToNode},
-    optimize::Optimize,
+    interpreter::{Interpret, Interpreter},
+    ir::{Builder, Inst, Lower, Value},
     parser::Rule,
-    symbol_table::SymbolTable,
+    span::Span,
+    syntax_tree::{parse_number, ParseContext},
 };
 
 /// Arithmetic operators
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArOp {
     Add,
     Sub,
     Mul,
     Div,
+    /// Multiplication by the power of two `rhs`, only introduced by optimizations
+    Shl,
+    /// Division by the power of two `rhs`, rounding toward zero like [`ArOp::Div`], only introduced
+    /// by optimizations
+    Shr,
 }
 
 impl ArOp {
-    pub fn execute(&self, lhs: i32, rhs: i32) -> i32 {
+    /// Computes the result of this operation
+    ///
+    /// Returns [`None`] on division by zero or overflow.
+    pub fn execute(&self, lhs: i32, rhs: i32) -> Option<i32> {
         match self {
-            ArOp::Add => lhs + rhs,
-            ArOp::Sub => lhs - rhs,
-            ArOp::Mul => lhs * rhs,
-            ArOp::Div => lhs / rhs,
+            ArOp::Add => lhs.checked_add(rhs),
+            ArOp::Sub => lhs.checked_sub(rhs),
+            ArOp::Mul => lhs.checked_mul(rhs),
+            ArOp::Div => lhs.checked_div(rhs),
+            ArOp::Shl => lhs.checked_mul(2i32.checked_pow(rhs as u32)?),
+            ArOp::Shr => lhs.checked_div(2i32.checked_pow(rhs as u32)?),
         }
     }
 }
@@ -40,6 +56,8 @@ impl Display for ArOp {
                 ArOp::Sub => "-",
                 ArOp::Mul => "*",
                 ArOp::Div => "/",
+                ArOp::Shl => "<<",
+                ArOp::Shr => ">>",
             }
         )
     }
@@ -57,30 +75,16 @@ impl From<Pair<'_, Rule>> for ArOp {
     }
 }
 
-impl Generate for ArOp {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        _: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Mnemonic::*, Register::*};
-
-        match self {
-            ArOp::Add => program.add(Add(R8, R9.into())),
-            ArOp::Sub => program.add(Sub(R8, R9.into())),
-            ArOp::Mul => program.add(IMul(R8, R9.into())),
-            ArOp::Div => program
-                .add(Mov(Rax, R8.into()))
-                .add(Xor(Rdx, Rdx.into()))
-                .add(IDiv(R9))
-                .add(Mov(R8, Rax.into())),
-        }
-    }
+/// An arithmetic expression
+#[derive(Debug, Clone)]
+pub struct Arexpr {
+    pub kind: ArexprKind,
+    pub span: Span,
 }
 
-/// An arithmetic expression
-#[derive(Debug)]
-pub enum Arexpr {
+/// The different kinds of [`Arexpr`]
+#[derive(Debug, Clone)]
+pub enum ArexprKind {
     Num(i32),
     Ident(String),
     BinExpr {
@@ -91,15 +95,108 @@ pub enum Arexpr {
 }
 
 impl Arexpr {
+    pub fn new(kind: ArexprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Creates a binary expression spanning from `lhs` to `rhs`
     pub fn binexpr(lhs: Arexpr, op: ArOp, rhs: Arexpr) -> Self {
-        Self::BinExpr {
-            lhs: Box::new(lhs),
-            op,
-            rhs: Box::new(rhs),
+        let span = lhs.span.to(rhs.span);
+
+        Self::new(
+            ArexprKind::BinExpr {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            },
+            span,
+        )
+    }
+
+    /// Calls `f` on the operands of this expression, then on itself
+    ///
+    /// Returns whether any call of `f` did.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Arexpr) -> bool) -> bool {
+        let mut changed = false;
+
+        if let ArexprKind::BinExpr { lhs, rhs, .. } = &mut self.kind {
+            changed |= lhs.visit_mut(f);
+            changed |= rhs.visit_mut(f);
+        }
+
+        changed | f(self)
+    }
+
+    /// Computes the value of this expression if it is constant
+    pub fn evaluate(&self) -> Option<i32> {
+        match &self.kind {
+            ArexprKind::Num(n) => Some(*n),
+            ArexprKind::Ident(_) => None,
+            ArexprKind::BinExpr { lhs, op, rhs } => op.execute(lhs.evaluate()?, rhs.evaluate()?),
+        }
+    }
+
+    /// Checks if two expressions compute the same thing, wherever they are in the source
+    pub fn same_as(&self, other: &Arexpr) -> bool {
+        match (&self.kind, &other.kind) {
+            (ArexprKind::Num(lhs), ArexprKind::Num(rhs)) => lhs == rhs,
+            (ArexprKind::Ident(lhs), ArexprKind::Ident(rhs)) => lhs == rhs,
+            (
+                ArexprKind::BinExpr { lhs, op, rhs },
+                ArexprKind::BinExpr {
+                    lhs: other_lhs,
+                    op: other_op,
+                    rhs: other_rhs,
+                },
+            ) => op == other_op && lhs.same_as(other_lhs) && rhs.same_as(other_rhs),
+            _ => false,
         }
     }
 
-    pub fn from_pair(value: Pair<'_, Rule>, _symbol_table: &mut SymbolTable) -> Self {
+    /// Checks if computing this expression could divide by zero
+    pub fn may_divide_by_zero(&self) -> bool {
+        match &self.kind {
+            ArexprKind::Num(_) | ArexprKind::Ident(_) => false,
+            ArexprKind::BinExpr { lhs, op, rhs } => {
+                (*op == ArOp::Div && rhs.evaluate().is_none_or(|n| n == 0))
+                    || lhs.may_divide_by_zero()
+                    || rhs.may_divide_by_zero()
+            }
+        }
+    }
+
+    /// The variables read by this expression, with duplicates
+    pub fn variables(&self) -> Vec<&str> {
+        match &self.kind {
+            ArexprKind::Num(_) => vec![],
+            ArexprKind::Ident(name) => vec![name.as_str()],
+            ArexprKind::BinExpr { lhs, rhs, .. } => {
+                let mut variables = lhs.variables();
+                variables.extend(rhs.variables());
+                variables
+            }
+        }
+    }
+
+    /// The number of registers needed to compute this expression, its Sethi-Ullman number
+    ///
+    /// A constant or a variable is used directly as the right operand of an operation, but needs
+    /// a register as the left one, which receives the result.
+    pub fn registers(&self, lhs: bool) -> usize {
+        match &self.kind {
+            ArexprKind::Num(_) | ArexprKind::Ident(_) => lhs as usize,
+            ArexprKind::BinExpr { lhs, rhs, .. } => {
+                let (lhs, rhs) = (lhs.registers(true), rhs.registers(false));
+                if lhs == rhs {
+                    lhs + 1
+                } else {
+                    lhs.max(rhs)
+                }
+            }
+        }
+    }
+
+    pub fn from_pair(value: Pair<'_, Rule>, _ctx: &mut ParseContext) -> Result<Self, SyntaxError> {
         use pest::pratt_parser::{Assoc, Op};
 
         let pratt = PrattParser::new()
@@ -107,67 +204,124 @@ impl Arexpr {
             .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left));
 
         pratt
-            .map_primary(|operand| match operand.as_rule() {
-                Rule::number => Self::Num(operand.as_str().trim().parse().unwrap()),
-                Rule::arexpr => Arexpr::from_pair(operand, _symbol_table),
-                Rule::ident => Self::Ident(operand.as_str().trim().into()),
-                rule => unreachable!("Expected operand, found {:?}", rule),
+            .map_primary(|operand| {
+                let span = operand.as_span().into();
+                Ok(match operand.as_rule() {
+                    Rule::number => Self::new(ArexprKind::Num(parse_number(&operand)?), span),
+                    Rule::arexpr => Arexpr::from_pair(operand, _ctx)?,
+                    Rule::ident => {
+                        Self::new(ArexprKind::Ident(operand.as_str().trim().into()), span)
+                    }
+                    rule => unreachable!("Expected operand, found {:?}", rule),
+                })
             })
-            .map_infix(|lhs, op, rhs| Arexpr::binexpr(lhs, op.into(), rhs))
+            .map_infix(|lhs, op, rhs| Ok(Arexpr::binexpr(lhs?, op.into(), rhs?)))
             .parse(value.into_inner())
     }
 }
 
+/// Writes the expression back as source, with parentheses around every nested operation
+impl Display for Arexpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ArexprKind::Num(n) => write!(f, "{n}"),
+            ArexprKind::Ident(name) => write!(f, "{name}"),
+            ArexprKind::BinExpr { lhs, op, rhs } => {
+                write_operand(f, lhs)?;
+                write!(f, " {op} ")?;
+                write_operand(f, rhs)
+            }
+        }
+    }
+}
+
+fn write_operand(f: &mut std::fmt::Formatter<'_>, operand: &Arexpr) -> std::fmt::Result {
+    match operand.kind {
+        ArexprKind::BinExpr { .. } => write!(f, "({operand})"),
+        _ => write!(f, "{operand}"),
+    }
+}
+
 impl ToNode for Arexpr {
     fn to_node(&self) -> Node {
-        match self {
-            Arexpr::Num(n) => n.to_node(),
-            Arexpr::Ident(name) => name.to_node(),
-            Arexpr::BinExpr { lhs, op, rhs } => Node::new(&op.to_string())
+        match &self.kind {
+            ArexprKind::Num(n) => n.to_node(),
+            ArexprKind::Ident(name) => name.to_node(),
+            ArexprKind::BinExpr { lhs, op, rhs } => Node::new(&op.to_string())
                 .add(lhs.to_node())
                 .add(rhs.to_node()),
         }
+        .tooltip(&self.span.to_string())
     }
 }
 
-impl Generate for Arexpr {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Mnemonic::*, Register::*};
+impl Check for Arexpr {
+    fn check(&self, checker: &mut Checker) {
+        match &self.kind {
+            ArexprKind::Num(_) => {}
+            ArexprKind::Ident(name) => checker.check_variable(name, self.span),
+            ArexprKind::BinExpr { lhs, op, rhs } => {
+                lhs.check(checker);
+                rhs.check(checker);
 
-        match self {
-            Arexpr::Num(n) => program.add(Push((*n).into())),
-            Arexpr::Ident(name) => symbol_table.access(name, program).add(Push(Rbx.into())),
-            Arexpr::BinExpr { lhs, op, rhs } => {
-                let program = lhs.generate(program, symbol_table);
-                let program = rhs.generate(program, symbol_table);
+                if matches!(op, ArOp::Div) && rhs.evaluate() == Some(0) {
+                    checker.errors.push(SyntaxError::DivisionByZero(self.span));
+                }
+            }
+        }
+    }
+}
 
-                let program = program.add(Pop(R9)).add(Pop(R8));
+impl Lower for Arexpr {
+    type Output = Value;
 
-                let program = op.generate(program, symbol_table);
+    fn lower(&self, builder: &mut Builder) -> Value {
+        match &self.kind {
+            ArexprKind::Num(n) => Value::Const(*n),
+            ArexprKind::Ident(name) => Value::Var(name.clone()),
+            ArexprKind::BinExpr { lhs, op, rhs } => {
+                // the operand needing more registers is computed first, so that fewer
+                // registers are used at once
+                let (lhs, rhs) = if rhs.registers(false) > lhs.registers(true) {
+                    let rhs = rhs.lower(builder);
+                    (lhs.lower(builder), rhs)
+                } else {
+                    (lhs.lower(builder), rhs.lower(builder))
+                };
+                let dst = builder.temp();
 
-                program.add(Push(R8.into()))
+                builder.emit(Inst::Binary {
+                    dst,
+                    op: *op,
+                    lhs,
+                    rhs,
+                });
+
+                Value::Temp(dst)
             }
         }
     }
 }
 
-impl Optimize for Arexpr {
-    fn optimize(self) -> Self {
-        match self {
-            Self::BinExpr { lhs, op, rhs } => {
-                let lhs = lhs.optimize();
-                let rhs = rhs.optimize();
+impl Interpret for Arexpr {
+    type Output = i32;
 
-                match (lhs, rhs) {
-                    (Arexpr::Num(n1), Arexpr::Num(n2)) => Arexpr::Num(op.execute(n1, n2)),
-                    (l, r) => Arexpr::binexpr(l, op, r),
-                }
+    fn interpret<R: Read, W: Write>(
+        &self,
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<i32, RuntimeError> {
+        match &self.kind {
+            ArexprKind::Num(n) => Ok(*n),
+            ArexprKind::Ident(ident) => Ok(interpreter.variable(ident)),
+            ArexprKind::BinExpr { lhs, op, rhs } => {
+                let lhs = lhs.interpret(interpreter)?;
+                let rhs = rhs.interpret(interpreter)?;
+
+                op.execute(lhs, rhs).ok_or(match op {
+                    ArOp::Div if rhs == 0 => RuntimeError::DivisionByZero(self.span),
+                    _ => RuntimeError::Overflow(self.span),
+                })
             }
-            _ => self,
         }
     }
 }
@@ -1,50 +1,65 @@
 use pest::iterators::Pair;
 
-use super::Stmt;
+use super::{parse_number, ParseContext, Stmt};
 use crate::{
+    check::{Check, Checker},
     error::SyntaxError,
-    generate::Generate,
     graphviz::{Node, ToNode},
-    optimize::Optimize,
+    ir::{Builder, Lower},
     parser::Rule,
-    symbol_table::SymbolTable,
+    span::Span,
 };
 
-static mut LAST_LINE: usize = 0;
-
 #[derive(Debug)]
 pub struct Line {
     number: usize,
     stmt: Stmt,
+    span: Span,
 }
 
 impl Line {
-    pub fn from_pair(
-        value: Pair<'_, Rule>,
-        symbol_table: &mut SymbolTable,
-    ) -> Result<Self, SyntaxError> {
+    /// Creates a line for optimization passes, spanning like its statement
+    ///
+    /// A line numbered like the one before it cannot be the target of a `GOTO`, which goes to the
+    /// first line with its number.
+    pub fn new(number: usize, stmt: Stmt) -> Self {
+        Self {
+            number,
+            span: stmt.span,
+            stmt,
+        }
+    }
+
+    pub fn from_pair(value: Pair<'_, Rule>, ctx: &mut ParseContext) -> Result<Self, SyntaxError> {
+        let span = value.as_span().into();
+
         if matches!(value.as_rule(), Rule::rem | Rule::NEWLINE) {
             return Ok(Self {
                 number: 0,
-                stmt: Stmt::NoOp,
+                stmt: Stmt::noop(span),
+                span,
             });
         }
 
         debug_assert_eq!(value.as_rule(), Rule::line);
 
         let mut number: Option<usize> = None;
-        let mut stmt: Stmt = Stmt::NoOp;
+        let mut stmt = Stmt::noop(span);
 
         for token in value.into_inner() {
             match token.as_rule() {
                 Rule::number => {
-                    number = Some(token.as_str().trim().parse().unwrap());
-                    if number.unwrap() <= unsafe { LAST_LINE } {
-                        return Err(SyntaxError::WrongLineNumber(token.line_col().0));
+                    number = Some(parse_number(&token)?);
+                    if number.unwrap() <= ctx.last_line {
+                        return Err(SyntaxError::WrongLineNumber {
+                            number: number.unwrap(),
+                            previous: ctx.last_line,
+                            span: token.as_span().into(),
+                        });
                     }
-                    unsafe { LAST_LINE = number.unwrap() };
+                    ctx.last_line = number.unwrap();
                 }
-                Rule::stmt => stmt = Stmt::from_pair(token, symbol_table)?,
+                Rule::stmt => stmt = Stmt::from_pair(token, ctx)?,
                 Rule::NEWLINE => {}
                 rule => unreachable!("Expected line, found {:?}", rule),
             }
@@ -52,51 +67,62 @@ impl Line {
 
         Ok(Self {
             number: number.unwrap_or_else(|| {
-                if matches!(stmt, Stmt::NoOp) {
+                if stmt.is_noop() {
                     0
                 } else {
-                    unsafe {
-                        LAST_LINE += 1;
-                        LAST_LINE
-                    }
+                    ctx.last_line += 1;
+                    ctx.last_line
                 }
             }),
             stmt,
+            span,
         })
     }
 
+    /// The number of this line, either from the source or automatically assigned
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
     /// Checks if the line is empty.
     ///
     /// A line can be empty because of the source program or because of optimizations.
     pub fn is_empty(&self) -> bool {
-        matches!(self.stmt, Stmt::NoOp)
+        self.stmt.is_noop()
+    }
+
+    pub fn stmt(&self) -> &Stmt {
+        &self.stmt
+    }
+
+    pub fn stmt_mut(&mut self) -> &mut Stmt {
+        &mut self.stmt
     }
 }
 
 impl ToNode for Line {
     fn to_node(&self) -> Node {
-        Node::new(&format!("line ({})", self.number)).add(self.stmt.to_node())
+        Node::new(&format!("line ({})", self.number))
+            .tooltip(&self.span.to_string())
+            .add(self.stmt.to_node())
     }
 }
 
-impl Generate for Line {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        if matches!(self.stmt, Stmt::NoOp) {
-            return program;
-        }
-
-        let program = program.label(&format!("line{}", self.number));
-        self.stmt.generate(program, symbol_table)
+impl Check for Line {
+    fn check(&self, checker: &mut Checker) {
+        self.stmt.check(checker);
     }
 }
 
-impl Optimize for Line {
-    fn optimize(mut self) -> Self {
-        self.stmt = self.stmt.optimize();
-        self
+impl Lower for Line {
+    type Output = ();
+
+    fn lower(&self, builder: &mut Builder) {
+        if self.is_empty() {
+            return;
+        }
+
+        builder.start_line(self.number, self.span);
+        self.stmt.lower(builder);
     }
 }
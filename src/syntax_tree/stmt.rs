@@ -1,18 +1,32 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
+
 use pest::iterators::Pair;
 
-use super::{Arexpr, Cond, Expr};
+use super::{parse_number, Arexpr, Cond, Expr, ExprKind};
 use crate::{
-    error::SyntaxError,
-    generate::Generate,
+    check::{Check, Checker},
+    error::{RuntimeError, SyntaxError},
     graphviz::{Node, ToNode},
-    optimize::Optimize,
+    interpreter::{Flow, Interpret, Interpreter},
+    ir::{Builder, Inst, Lower, Runtime, Terminator},
     parser::Rule,
-    symbol_table::{SymbolTable, Type},
-    syntax_tree::COND_COUNT,
+    span::Span,
+    symbol_table::Type,
+    syntax_tree::ParseContext,
 };
 
 #[derive(Debug)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+/// The different kinds of [`Stmt`]
+#[derive(Debug)]
+pub enum StmtKind {
     End,
     Goto(u32),
     If {
@@ -28,61 +42,181 @@ pub enum Stmt {
 }
 
 impl Stmt {
-    pub fn from_pair(
-        value: Pair<'_, Rule>,
-        symbol_table: &mut SymbolTable,
-    ) -> Result<Self, SyntaxError> {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Creates an empty statement at the given location
+    pub fn noop(span: Span) -> Self {
+        Self::new(StmtKind::NoOp, span)
+    }
+
+    pub fn from_pair(value: Pair<'_, Rule>, ctx: &mut ParseContext) -> Result<Self, SyntaxError> {
+        let span = value.as_span().into();
         let mut pairs = value.into_inner();
         let stmt = pairs.next().unwrap();
 
-        Ok(match stmt.as_rule() {
-            Rule::end | Rule::r#return => Stmt::End,
-            Rule::goto | Rule::gosub => {
-                let line = pairs.next().unwrap();
-                Stmt::Goto(line.as_str().parse()?)
-            }
-            Rule::r#if => Stmt::If {
-                cond: pairs.next().unwrap().into(),
-                then: Box::new(Self::from_pair(pairs.next().unwrap(), symbol_table)?),
+        let kind = match stmt.as_rule() {
+            Rule::end | Rule::r#return => StmtKind::End,
+            Rule::goto | Rule::gosub => StmtKind::Goto(parse_number(&pairs.next().unwrap())?),
+            Rule::r#if => StmtKind::If {
+                cond: Cond::from_pair(pairs.next().unwrap())?,
+                then: Box::new(Self::from_pair(pairs.next().unwrap(), ctx)?),
                 els: {
                     if pairs.next().is_some() {
-                        Some(Box::new(Self::from_pair(
-                            pairs.next().unwrap(),
-                            symbol_table,
-                        )?))
+                        Some(Box::new(Self::from_pair(pairs.next().unwrap(), ctx)?))
                     } else {
                         None
                     }
                 },
             },
             Rule::input => {
-                let ident = pairs.next().unwrap().as_str();
-                symbol_table.insert(ident, Type::Int);
-                Stmt::Input(ident.into())
+                let ident = pairs.next().unwrap();
+                ctx.symbol_table
+                    .insert(ident.as_str(), Type::Int, ident.as_span().into());
+                StmtKind::Input(ident.as_str().into())
             }
             Rule::r#let => {
                 let ident = pairs.next().unwrap();
                 let expr = pairs.next().unwrap();
 
+                let ident_span = ident.as_span().into();
                 let ident = ident.as_str().trim().to_string();
-                let expr = Arexpr::from_pair(expr, symbol_table);
+                let expr = Arexpr::from_pair(expr, ctx)?;
 
-                symbol_table.insert(&ident, Type::Int);
+                ctx.symbol_table.insert(&ident, Type::Int, ident_span);
 
-                Stmt::Let(ident, expr)
+                StmtKind::Let(ident, expr)
             }
-            Rule::print => Stmt::Print(Expr::from_pair(pairs.next().unwrap(), symbol_table)),
+            Rule::print => StmtKind::Print(Expr::from_pair(pairs.next().unwrap(), ctx)?),
             rule => unimplemented!("Unknown statement {:?}", rule),
-        })
+        };
+
+        Ok(Self::new(kind, span))
+    }
+
+    /// Checks if this statement does nothing.
+    pub fn is_noop(&self) -> bool {
+        matches!(self.kind, StmtKind::NoOp)
+    }
+
+    /// The arithmetic expression computed by this statement, if any
+    pub fn arexpr_mut(&mut self) -> Option<&mut Arexpr> {
+        match &mut self.kind {
+            StmtKind::Let(_, arexpr)
+            | StmtKind::Print(Expr {
+                kind: ExprKind::Arexpr(arexpr),
+                ..
+            }) => Some(arexpr),
+            _ => None,
+        }
+    }
+
+    /// The lines this statement can jump to
+    pub fn targets(&self) -> Vec<u32> {
+        match &self.kind {
+            StmtKind::Goto(line) => vec![*line],
+            StmtKind::If { then, els, .. } => {
+                let mut targets = then.targets();
+                if let Some(els) = els {
+                    targets.extend(els.targets());
+                }
+                targets
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Checks if the execution can go on to the next line after this statement
+    pub fn continues(&self) -> bool {
+        match &self.kind {
+            StmtKind::End | StmtKind::Goto(_) => false,
+            StmtKind::If { then, els, .. } => {
+                then.continues() || els.as_ref().is_none_or(|els| els.continues())
+            }
+            StmtKind::Input(_) | StmtKind::Let(..) | StmtKind::Print(_) | StmtKind::NoOp => true,
+        }
+    }
+
+    /// The variables read by this statement, including the ones nested in `IF`
+    pub fn reads(&self) -> Vec<&str> {
+        match &self.kind {
+            StmtKind::If { cond, then, els } => {
+                let mut reads = cond.variables();
+                reads.extend(then.reads());
+                if let Some(els) = els {
+                    reads.extend(els.reads());
+                }
+                reads
+            }
+            StmtKind::Let(_, arexpr)
+            | StmtKind::Print(Expr {
+                kind: ExprKind::Arexpr(arexpr),
+                ..
+            }) => arexpr.variables(),
+            _ => vec![],
+        }
+    }
+
+    /// The variables written by this statement, including the ones nested in `IF`
+    pub fn writes(&self) -> Vec<&str> {
+        match &self.kind {
+            StmtKind::If { then, els, .. } => {
+                let mut writes = then.writes();
+                if let Some(els) = els {
+                    writes.extend(els.writes());
+                }
+                writes
+            }
+            StmtKind::Input(name) | StmtKind::Let(name, _) => vec![name.as_str()],
+            _ => vec![],
+        }
+    }
+
+    /// Calls `f` on this statement, then on the statements nested in it
+    ///
+    /// Returns whether any call of `f` did.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Stmt) -> bool) -> bool {
+        let mut changed = f(self);
+
+        if let StmtKind::If { then, els, .. } = &mut self.kind {
+            changed |= then.visit_mut(f);
+            if let Some(els) = els {
+                changed |= els.visit_mut(f);
+            }
+        }
+
+        changed
+    }
+}
+
+/// Writes the statement back as source, empty statements as nothing
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            StmtKind::End => write!(f, "END"),
+            StmtKind::Goto(line) => write!(f, "GOTO {line}"),
+            StmtKind::If { cond, then, els } => {
+                write!(f, "IF {cond} THEN {then}")?;
+                if let Some(els) = els {
+                    write!(f, " ELSE {els}")?;
+                }
+                Ok(())
+            }
+            StmtKind::Input(ident) => write!(f, "INPUT {ident}"),
+            StmtKind::Let(ident, arexpr) => write!(f, "LET {ident} = {arexpr}"),
+            StmtKind::Print(expr) => write!(f, "PRINT {expr}"),
+            StmtKind::NoOp => Ok(()),
+        }
     }
 }
 
 impl ToNode for Stmt {
     fn to_node(&self) -> Node {
-        match self {
-            Stmt::End => Node::new("end"),
-            Stmt::Goto(line) => Node::new("goto").add(line.to_node()),
-            Stmt::If { cond, then, els } => {
+        match &self.kind {
+            StmtKind::End => Node::new("end"),
+            StmtKind::Goto(line) => Node::new("goto").add(line.to_node()),
+            StmtKind::If { cond, then, els } => {
                 let node = Node::new("if").add(cond.to_node()).add(then.to_node());
                 if let Some(els) = els {
                     node.add(els.to_node())
@@ -90,105 +224,149 @@ impl ToNode for Stmt {
                     node
                 }
             }
-            Stmt::Input(ident) => Node::new("input").add(ident.to_node()),
-            Stmt::Let(ident, value) => Node::new("let").add(ident.to_node()).add(value.to_node()),
-            Stmt::Print(expr) => Node::new("print").add(expr.to_node()),
-            Stmt::NoOp => Node::new(""),
+            StmtKind::Input(ident) => Node::new("input").add(ident.to_node()),
+            StmtKind::Let(ident, value) => {
+                Node::new("let").add(ident.to_node()).add(value.to_node())
+            }
+            StmtKind::Print(expr) => Node::new("print").add(expr.to_node()),
+            StmtKind::NoOp => Node::new(""),
         }
+        .tooltip(&self.span.to_string())
     }
 }
 
-impl Generate for Stmt {
-    fn generate(
-        &self,
-        program: tiny_elf::asm::Program,
-        symbol_table: &mut SymbolTable,
-    ) -> tiny_elf::asm::Program {
-        use tiny_elf::asm::{Memory, Mnemonic::*, Register::*};
-
-        static mut LITERAL_COUNT: usize = 0;
+impl Check for Stmt {
+    fn check(&self, checker: &mut Checker) {
+        match &self.kind {
+            StmtKind::Goto(line) => {
+                if !checker.lines.contains(&(*line as usize)) {
+                    checker.errors.push(SyntaxError::UndefinedLine {
+                        line: *line,
+                        span: self.span,
+                    });
+                }
+            }
+            StmtKind::If { cond, then, els } => {
+                cond.check(checker);
+                then.check(checker);
+                if let Some(els) = els {
+                    els.check(checker);
+                }
+            }
+            StmtKind::Let(_, arexpr) => arexpr.check(checker),
+            StmtKind::Print(expr) => expr.check(checker),
+            StmtKind::End | StmtKind::Input(_) | StmtKind::NoOp => {}
+        }
+    }
+}
 
-        match self {
-            Stmt::End => program.add(Jmp("exit".into())),
-            Stmt::Goto(line) => program.add(Jmp(Memory::from(format!("line{line}")))),
-            Stmt::If { cond, then, els } => {
-                let mut program = cond.generate(program, symbol_table);
+impl Lower for Stmt {
+    type Output = ();
 
-                let count = unsafe { COND_COUNT };
-                let endif_label = format!("fi{}", count);
+    fn lower(&self, builder: &mut Builder) {
+        match &self.kind {
+            StmtKind::End => builder.end(Terminator::Exit),
+            StmtKind::Goto(line) => {
+                let target = builder.line_block(*line as usize);
+                builder.end(Terminator::Jump(target));
+            }
+            StmtKind::If { cond, then, els } => {
+                let (lhs, relop, rhs) = cond.lower(builder);
+                let then_block = builder.new_block();
+                let endif_block = builder.new_block();
 
                 // else
                 if let Some(els) = els {
-                    program = program.label(&format!("else{}", count));
-                    program = els
-                        .generate(program, symbol_table)
-                        .add(Jmp(endif_label.clone().into()));
+                    let else_block = builder.new_block();
+                    builder.end(Terminator::Branch {
+                        relop,
+                        lhs,
+                        rhs,
+                        then: then_block,
+                        els: else_block,
+                    });
+                    builder.start(else_block);
+                    els.lower(builder);
+                    builder.end(Terminator::Jump(endif_block));
                 } else {
-                    program = program.add(Jmp(endif_label.clone().into()))
+                    builder.end(Terminator::Branch {
+                        relop,
+                        lhs,
+                        rhs,
+                        then: then_block,
+                        els: endif_block,
+                    });
                 }
 
                 // then
-                program = program.label(&format!("then{}", count));
-                program = then.generate(program, symbol_table);
+                builder.start(then_block);
+                then.lower(builder);
 
-                program.label(&endif_label)
+                builder.start(endif_block);
             }
-            Stmt::Input(ident) => {
-                let end_addr = symbol_table.get(ident).unwrap().end_addr() as i32;
-
-                let program = program
-                    .add(Mov(Rsi, R15.into()))
-                    .add(Sub(Rsi, end_addr.into()))
-                    .add(Mov(Rdx, 8.into()))
-                    .add(Call("read".into()));
-
-                let program = symbol_table.access(ident, program).add(Sub(Rbx, 48.into()));
-
-                symbol_table.write(ident, Rbx.into(), program)
+            StmtKind::Input(ident) => builder.emit(Inst::Call(Runtime::Input(ident.clone()))),
+            StmtKind::Let(ident, arexpr) => {
+                let value = arexpr.lower(builder);
+                builder.emit(Inst::Store {
+                    var: ident.clone(),
+                    value,
+                });
             }
-            Stmt::Let(ident, arexpr) => {
-                let program = arexpr.generate(program, symbol_table).add(Pop(Rbx));
-                symbol_table.write(ident, Rbx.into(), program)
+            StmtKind::Print(Expr {
+                kind: ExprKind::String(str),
+                ..
+            }) => {
+                let index = builder.string(str);
+                builder.emit(Inst::Call(Runtime::PrintString(index)));
             }
-            Stmt::Print(Expr::String(str)) => {
-                let str = str.replace(r"\n", "\n").replace(r"\t", "\t");
-                let label = format!("literal{}", unsafe {
-                    LITERAL_COUNT += 1;
-                    LITERAL_COUNT
-                });
-
-                program
-                    .add(Mov(Rsi, Memory::from(label.as_str()).into()))
-                    .add(Mov(Rdx, (str.len() as i32).into()))
-                    .add(Call("print".into()))
-                    .insert_data(&label, &str)
-            }
-            Stmt::Print(Expr::Arexpr(arexpr)) => arexpr
-                .generate(program, symbol_table)
-                .add(Pop(Rsi))
-                .add(Call("printn".into())),
-            Stmt::NoOp => program,
+            StmtKind::Print(Expr {
+                kind: ExprKind::Arexpr(arexpr),
+                ..
+            }) => {
+                let value = arexpr.lower(builder);
+                builder.emit(Inst::Call(Runtime::PrintNumber(value)));
+            }
+            StmtKind::NoOp => {}
         }
     }
 }
 
-impl Optimize for Stmt {
-    fn optimize(self) -> Self {
-        match self {
-            Stmt::Print(expr) => Stmt::Print(expr.optimize()),
-            Stmt::Let(ident, expr) => Stmt::Let(ident, expr.optimize()),
-            Stmt::If { cond, then, els } => {
-                if let Ok(b) = cond.try_execute() {
-                    if b {
-                        *then
-                    } else {
-                        els.map_or(Stmt::NoOp, |s| *s)
-                    }
-                } else {
-                    Stmt::If { cond, then, els }
+impl Interpret for Stmt {
+    type Output = Flow;
+
+    fn interpret<R: Read, W: Write>(
+        &self,
+        interpreter: &mut Interpreter<R, W>,
+    ) -> Result<Flow, RuntimeError> {
+        match &self.kind {
+            StmtKind::End => return Ok(Flow::End),
+            StmtKind::Goto(line) => return Ok(Flow::Goto(*line)),
+            StmtKind::If { cond, then, els } => {
+                if cond.interpret(interpreter)? {
+                    return then.interpret(interpreter);
+                } else if let Some(els) = els {
+                    return els.interpret(interpreter);
                 }
             }
-            _ => self,
+            StmtKind::Input(ident) => interpreter.input(ident)?,
+            StmtKind::Let(ident, arexpr) => {
+                let value = arexpr.interpret(interpreter)?;
+                interpreter.set_variable(ident, value);
+            }
+            StmtKind::Print(Expr {
+                kind: ExprKind::String(str),
+                ..
+            }) => interpreter.print(str)?,
+            StmtKind::Print(Expr {
+                kind: ExprKind::Arexpr(arexpr),
+                ..
+            }) => {
+                let value = arexpr.interpret(interpreter)?;
+                interpreter.print(&value.to_string())?;
+            }
+            StmtKind::NoOp => {}
         }
+
+        Ok(Flow::Next)
     }
 }
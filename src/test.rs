@@ -0,0 +1,235 @@
+//! Runs Tiny BASIC programs and checks their behavior against annotations in their comments.
+//!
+//! - `REM STDIN: <text>` is given as input, several of them are joined with new lines
+//! - `REM EXPECT: <text>` is a line of the expected output, `<name>.out` is used if there is none
+//! - `REM EXPECT EXIT: <code>` is the expected exit code, 0 by default
+//! - `REM EXPECT ERROR: <code>` expects the compilation to fail with this diagnostic code, e.g.
+//!   `E0001`
+//! - `REM EXPECT RUNTIME ERROR: <code>` expects the program to stop with this runtime error,
+//!   executables can only report a division by zero (`E0006`)
+//!
+//! Trailing new lines are ignored when comparing outputs.
+
+use std::{
+    fs, io,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+};
+
+use tbc::{
+    assemble, check, error::RuntimeError, interpreter::Interpreter, link, optimize,
+    optimize::Passes, parse, span::Span,
+};
+
+use crate::{cli::TestCli, output::run_executable, verify::SIGFPE};
+
+/// The expected behavior of a program
+#[derive(Debug, Default)]
+struct Spec {
+    stdin: Vec<String>,
+    stdout: Option<String>,
+    exit: i32,
+    error: Option<String>,
+    runtime_error: Option<String>,
+}
+
+impl Spec {
+    /// Reads the annotations of a program, `out` being the content of its `.out` file if any
+    fn parse(source: &str, out: Option<String>) -> Result<Self, String> {
+        let mut spec = Spec::default();
+        let mut expected: Vec<&str> = Vec::new();
+
+        for line in source.lines() {
+            let Some(annotation) = line.trim_start().strip_prefix("REM ") else {
+                continue;
+            };
+            let annotation = annotation.trim_start();
+
+            if let Some(exit) = annotation.strip_prefix("EXPECT EXIT:") {
+                spec.exit = exit
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("wrong exit code `{}`: {e}", exit.trim()))?;
+            } else if let Some(code) = annotation.strip_prefix("EXPECT RUNTIME ERROR:") {
+                spec.runtime_error = Some(code.trim().to_string());
+            } else if let Some(code) = annotation.strip_prefix("EXPECT ERROR:") {
+                spec.error = Some(code.trim().to_string());
+            } else if let Some(text) = annotation.strip_prefix("EXPECT:") {
+                expected.push(text.strip_prefix(' ').unwrap_or(text));
+            } else if let Some(text) = annotation.strip_prefix("STDIN:") {
+                spec.stdin
+                    .push(text.strip_prefix(' ').unwrap_or(text).to_string());
+            }
+        }
+
+        spec.stdout = if expected.is_empty() {
+            out
+        } else {
+            Some(expected.join("\n"))
+        };
+
+        Ok(spec)
+    }
+}
+
+/// What a program did
+struct Outcome {
+    stdout: String,
+    /// The exit code, or a description of how the program stopped
+    exit: Result<i32, String>,
+    /// The code of the runtime error that stopped the program, if known
+    runtime_error: Option<&'static str>,
+}
+
+/// Tests every program, printing a summary
+///
+/// Returns the number of failed tests.
+pub fn run(cli: &TestCli) -> io::Result<usize> {
+    let files = files(&cli.path)?;
+    let mut failed = 0;
+
+    println!(
+        "running {} test{}",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" }
+    );
+
+    for file in &files {
+        match test(file, cli.interpret) {
+            Ok(()) => println!("test {} ... ok", file.display()),
+            Err(reason) => {
+                println!("test {} ... FAILED", file.display());
+                for line in reason.lines() {
+                    println!("    {line}");
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "\ntest result: {}. {} passed; {failed} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        files.len() - failed
+    );
+
+    Ok(failed)
+}
+
+/// The `.tb` files of a directory in alphabetical order, or the given file
+fn files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "tb") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Runs a single program, returning why it failed if it did
+fn test(file: &Path, interpret: bool) -> Result<(), String> {
+    let source = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let out = fs::read_to_string(file.with_extension("out")).ok();
+    let spec = Spec::parse(&source, out)?;
+
+    let parsed = parse(&source).and_then(|parsed| check(&parsed).map(|_| parsed));
+
+    let parsed = match (parsed, &spec.error) {
+        (Ok(parsed), None) => parsed,
+        (Ok(_), Some(code)) => return Err(format!("expected error {code}, but it compiles")),
+        (Err(e), Some(code)) if e.diagnostics().iter().any(|d| d.code == code) => return Ok(()),
+        (Err(e), _) => {
+            let diagnostics: Vec<String> = e
+                .diagnostics()
+                .iter()
+                .filter(|d| d.is_error())
+                .map(|d| format!("{}:{}: {}", d.code, d.span, d.message))
+                .collect();
+            return Err(format!(
+                "compilation failed with {e}:\n{}",
+                diagnostics.join("\n")
+            ));
+        }
+    };
+
+    let input = spec.stdin.join("\n");
+    let passes = Passes::default();
+    let ost = optimize(parsed.ast, &passes);
+
+    let outcome = if interpret {
+        let mut stdout = Vec::new();
+        let (exit, runtime_error) = match Interpreter::new(input.as_bytes(), &mut stdout).run(&ost)
+        {
+            Ok(()) => (Ok(0), None),
+            Err(RuntimeError::Io(e)) => return Err(e.to_string()),
+            Err(e) => (Err(format!("runtime error: {e}")), Some(e.code())),
+        };
+
+        Outcome {
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            exit,
+            runtime_error,
+        }
+    } else {
+        let program = assemble(&ost, &parsed.symbol_table, &passes);
+        let name = file.file_stem().unwrap_or_default().to_string_lossy();
+        let output = run_executable(&format!("{name}.elf"), &link(&program), input.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        // executables dividing by zero are killed by the processor exception
+        let runtime_error = (output.status.signal() == Some(SIGFPE))
+            .then(|| RuntimeError::DivisionByZero(Span::default()).code());
+
+        Outcome {
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            exit: output.status.code().ok_or(output.status.to_string()),
+            runtime_error,
+        }
+    };
+
+    compare(&spec, &outcome)
+}
+
+fn compare(spec: &Spec, outcome: &Outcome) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    match (&spec.runtime_error, &outcome.exit) {
+        (Some(code), _) if outcome.runtime_error == Some(code.as_str()) => {}
+        (Some(code), Ok(exit)) => errors.push(format!(
+            "expected runtime error {code}, found exit code {exit}"
+        )),
+        (Some(code), Err(status)) => {
+            errors.push(format!("expected runtime error {code}, found {status}"))
+        }
+        (None, Ok(code)) if *code == spec.exit => {}
+        (None, Ok(code)) => errors.push(format!("expected exit code {}, found {code}", spec.exit)),
+        (None, Err(status)) => {
+            errors.push(format!("expected exit code {}, found {status}", spec.exit))
+        }
+    }
+
+    if let Some(expected) = &spec.stdout {
+        let expected = expected.trim_end_matches('\n');
+        let actual = outcome.stdout.trim_end_matches('\n');
+
+        if expected != actual {
+            errors.push(format!(
+                "expected output: {expected:?}\nactual output:   {actual:?}"
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
@@ -0,0 +1,163 @@
+//! Differential testing of the optimizations.
+//!
+//! The same program is run by the interpreter, then as an unoptimized and an optimized
+//! executable, with the same input. They must all print the same thing and exit the same way.
+//! A division by zero stops the interpreter with an error and kills the executables with SIGFPE,
+//! both are reported as the same status. Only the interpreter checks overflows, when it stops on
+//! one only the two executables are compared.
+
+use std::{
+    io::{self, Read},
+    os::unix::process::ExitStatusExt,
+};
+
+use tbc::{
+    assemble, error::RuntimeError, interpreter::Interpreter, link, optimize, optimize::Passes,
+    Parsed,
+};
+
+use crate::output::run_executable;
+
+/// What a program did
+struct Outcome {
+    name: &'static str,
+    stdout: Vec<u8>,
+    status: String,
+    /// The interpreter stopped on an overflow that the executables do not detect
+    overflow: bool,
+}
+
+/// The status of a program stopped by a division by zero
+const DIVISION_BY_ZERO: &str = "division by zero";
+/// The signal sent to a process dividing by zero
+pub const SIGFPE: i32 = 8;
+
+/// Runs every version of the program on the standard input of the compiler
+///
+/// Returns whether they all behave the same, reporting the first difference otherwise.
+pub fn verify(parsed: Parsed, passes: &Passes) -> io::Result<bool> {
+    let mut input = Vec::new();
+    io::stdin().read_to_end(&mut input)?;
+
+    let unoptimized = assemble(&parsed.ast, &parsed.symbol_table, &Passes::none());
+    let mut outcomes = vec![interpret(&parsed, &input)?];
+
+    let ost = optimize(parsed.ast, passes);
+    let optimized = assemble(&ost, &parsed.symbol_table, passes);
+
+    outcomes.push(execute("udump.elf", &link(&unoptimized), &input)?);
+    outcomes.push(execute("dump.elf", &link(&optimized), &input)?);
+
+    let compared = if outcomes[0].overflow {
+        eprintln!("{}, only comparing the executables", outcomes[0].status);
+        &outcomes[1..]
+    } else {
+        &outcomes[..]
+    };
+
+    let reference = &compared[0];
+    for outcome in &compared[1..] {
+        if let Some(difference) = difference(reference, outcome) {
+            eprintln!("{difference}");
+            return Ok(false);
+        }
+    }
+
+    println!(
+        "{} agree: {}",
+        compared
+            .iter()
+            .map(|o| o.name)
+            .collect::<Vec<_>>()
+            .join(", "),
+        reference.status
+    );
+    Ok(true)
+}
+
+fn interpret(parsed: &Parsed, input: &[u8]) -> io::Result<Outcome> {
+    let mut stdout = Vec::new();
+    let result = Interpreter::new(input, &mut stdout).run(&parsed.ast);
+    let overflow = matches!(result, Err(RuntimeError::Overflow(_)));
+
+    let status = match result {
+        Ok(()) => "exit status: 0".to_string(),
+        Err(RuntimeError::Io(e)) => return Err(e),
+        Err(RuntimeError::DivisionByZero(_)) => DIVISION_BY_ZERO.to_string(),
+        Err(e) => format!("runtime error: {e} at {}", e.span().unwrap_or_default()),
+    };
+
+    Ok(Outcome {
+        name: "interpreter",
+        stdout,
+        status,
+        overflow,
+    })
+}
+
+/// Runs an executable with the given input
+fn execute(name: &'static str, elf: &[u8], input: &[u8]) -> io::Result<Outcome> {
+    let output = run_executable(name, elf, input)?;
+
+    let status = match output.status.signal() {
+        Some(SIGFPE) => DIVISION_BY_ZERO.to_string(),
+        _ => output.status.to_string(),
+    };
+
+    Ok(Outcome {
+        name,
+        stdout: output.stdout,
+        status,
+        overflow: false,
+    })
+}
+
+/// Describes the first difference between two outcomes, if any
+fn difference(expected: &Outcome, actual: &Outcome) -> Option<String> {
+    if expected.stdout != actual.stdout {
+        let index = expected
+            .stdout
+            .iter()
+            .zip(&actual.stdout)
+            .position(|(e, a)| e != a)
+            .unwrap_or(expected.stdout.len().min(actual.stdout.len()));
+        let line = expected.stdout[..index]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1;
+
+        return Some(format!(
+            "{} and {} print different things from byte {index}, line {line}:\n  {}: {:?}\n  {}: {:?}",
+            expected.name,
+            actual.name,
+            expected.name,
+            line_at(&expected.stdout, index),
+            actual.name,
+            line_at(&actual.stdout, index),
+        ));
+    }
+
+    if expected.status != actual.status {
+        return Some(format!(
+            "{} and {} exit differently:\n  {}: {}\n  {}: {}",
+            expected.name, actual.name, expected.name, expected.status, actual.name, actual.status
+        ));
+    }
+
+    None
+}
+
+/// The line of the output containing the given byte
+fn line_at(output: &[u8], index: usize) -> String {
+    let start = output[..index.min(output.len())]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let end = output[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(output.len(), |i| start + i);
+
+    String::from_utf8_lossy(&output[start..end]).into_owned()
+}